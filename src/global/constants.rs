@@ -3,4 +3,31 @@ pub const PACKAGE_NAME: &str = env!("CARGO_PKG_NAME");
 pub const DEFAULT_MQTT_TIMEOUT: u64 = 15;
 pub const DEFAULT_MQTT_RECONNECT_TIMEOUT: u64 = 300;
 pub const MAXIMAL_CLIENT_ID_LENGTH: usize = 23;
+pub const PAYLOAD_VERSION_MAJOR: u64 = 2;
+pub const PAYLOAD_VERSION_MINOR: u64 = 0;
+pub const PAYLOAD_VERSION_1_MINOR: u64 = 1;
+pub const DEFAULT_MAX_PAYLOAD_SIZE: usize = 1048576;
 pub const DEFAULT_SSH_DIRECTORY: &str = "/etc/ssh";
+pub const DEFAULT_FILESYSTEM_POLL_INTERVAL: u64 = 30;
+pub const FILESYSTEM_INOTIFY_CHECK_MS: u64 = 200;
+pub const DEFAULT_ETCD_TIMEOUT: u64 = 15;
+pub const DEFAULT_ETCD_PROGRESS_TIMEOUT: u64 = 900;
+pub const DEFAULT_HTTP_INTERVAL: u64 = 300;
+pub const DEFAULT_HTTP_TIMEOUT: u64 = 15;
+pub const DEFAULT_REDIS_TIMEOUT: u64 = 15;
+pub const REDIS_STOP_CHECK_MS: u64 = 1000;
+pub const SUBSCRIBE_POLL_INTERVAL_MS: u64 = 1000;
+pub const INITIAL_SYNC_QUIET_PERIOD: u64 = 5;
+pub const INITIAL_SYNC_MAX_PERIOD: u64 = 60;
+
+pub const EXIT_USAGE_ERROR: i32 = 1;
+pub const EXIT_CONFIG_ERROR: i32 = 2;
+pub const EXIT_IO_ERROR: i32 = 3;
+pub const EXIT_MQTT_ERROR: i32 = 4;
+pub const EXIT_PAYLOAD_ERROR: i32 = 5;
+pub const EXIT_VALIDATION_ERROR: i32 = 6;
+pub const EXIT_REDIS_ERROR: i32 = 7;
+pub const EXIT_ETCD_ERROR: i32 = 8;
+pub const EXIT_HTTP_ERROR: i32 = 9;
+pub const EXIT_BACKEND_ERROR: i32 = 10;
+pub const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
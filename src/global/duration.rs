@@ -0,0 +1,24 @@
+use crate::error::Error;
+
+// Parse durations like 3600, 90m, 12h, 30d or 2w into seconds
+pub fn parse(s: &str) -> Result<u64, Error> {
+    let s = s.trim();
+    let (number, multiplier) = match s.char_indices().last() {
+        Some((i, 's')) => (&s[..i], 1),
+        Some((i, 'm')) => (&s[..i], 60),
+        Some((i, 'h')) => (&s[..i], 3600),
+        Some((i, 'd')) => (&s[..i], 86400),
+        Some((i, 'w')) => (&s[..i], 604800),
+        Some(_) => (s, 1),
+        None => return Err(Error::Validation("empty duration".to_string())),
+    };
+
+    // Durations are compared with UNIX timestamps, so they must fit into an i64
+    match number.parse::<u64>() {
+        Ok(v) => match v.checked_mul(multiplier) {
+            Some(v) if v <= i64::MAX as u64 => Ok(v),
+            _ => Err(Error::Validation(format!("duration {} is too large", s))),
+        },
+        Err(e) => Err(Error::Validation(format!("invalid duration {}: {}", s, e))),
+    }
+}
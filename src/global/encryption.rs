@@ -0,0 +1,221 @@
+use crate::error::Error;
+
+use std::borrow::Cow;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+// Header of the binary age format
+const AGE_MAGIC: &[u8] = b"age-encryption.org/";
+
+// Key data is encrypted with age (https://age-encryption.org) to one or more X25519 recipients.
+// Encryption only hides the key data from the operators of the backend, anybody knowing a
+// recipient can publish encrypted key data
+pub fn validate_recipients(recipients: &[String]) -> Result<(), Error> {
+    parse_recipients(recipients)?;
+    Ok(())
+}
+
+fn parse_recipients(recipients: &[String]) -> Result<Vec<age::x25519::Recipient>, Error> {
+    let mut result = Vec::new();
+    for r in recipients.iter() {
+        match age::x25519::Recipient::from_str(r) {
+            Ok(v) => result.push(v),
+            Err(e) => {
+                return Err(Error::Config(format!("invalid recipient {}: {}", r, e)));
+            }
+        };
+    }
+    Ok(result)
+}
+
+pub fn encrypt(raw: Vec<u8>, recipients: &[String]) -> Result<Vec<u8>, Error> {
+    if recipients.is_empty() {
+        return Ok(raw);
+    }
+
+    let recipients = parse_recipients(recipients)?;
+    let encryptor = match age::Encryptor::with_recipients(
+        recipients.iter().map(|r| r as &dyn age::Recipient),
+    ) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(Error::Payload(format!("can't encrypt key data: {}", e)));
+        }
+    };
+
+    let mut result = Vec::new();
+    let mut writer = encryptor.wrap_output(&mut result)?;
+    writer.write_all(&raw)?;
+    writer.finish()?;
+    Ok(result)
+}
+
+pub fn is_encrypted(raw: &[u8]) -> bool {
+    raw.starts_with(AGE_MAGIC)
+}
+
+// Identities aren't Send, so they must be loaded by the thread using them
+pub struct Identities {
+    file: String,
+    identities: Vec<Box<dyn age::Identity>>,
+}
+
+impl Identities {
+    pub fn from_file(file: &str) -> Result<Self, Error> {
+        let parsed = match age::IdentityFile::from_file(file.to_string()) {
+            Ok(v) => v,
+            Err(e) => {
+                return Err(Error::Config(format!(
+                    "can't read identity file {}: {}",
+                    file, e
+                )));
+            }
+        };
+        let identities = match parsed.into_identities() {
+            Ok(v) => v,
+            Err(e) => {
+                return Err(Error::Config(format!(
+                    "invalid identity file {}: {}",
+                    file, e
+                )));
+            }
+        };
+        if identities.is_empty() {
+            return Err(Error::Config(format!(
+                "no identity found in identity file {}",
+                file
+            )));
+        }
+
+        Ok(Identities {
+            file: file.to_string(),
+            identities,
+        })
+    }
+}
+
+// Unencrypted payloads are returned unchanged. Decryption stops after limit bytes
+pub fn decrypt<'a>(
+    raw: &'a [u8],
+    identities: Option<&Identities>,
+    limit: usize,
+) -> Result<Cow<'a, [u8]>, Error> {
+    if !is_encrypted(raw) {
+        return Ok(Cow::Borrowed(raw));
+    }
+
+    let identities = match identities {
+        Some(v) => v,
+        None => {
+            return Err(Error::Payload(
+                "key data is encrypted, but no identity file is configured".to_string(),
+            ));
+        }
+    };
+
+    if raw.len() > limit {
+        return Err(Error::Validation(format!(
+            "payload size of {} bytes exceeds the maximal size of {} bytes",
+            raw.len(),
+            limit
+        )));
+    }
+
+    let decryptor = match age::Decryptor::new_buffered(raw) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(Error::Payload(format!("can't decrypt key data: {}", e)));
+        }
+    };
+    let reader = match decryptor.decrypt(identities.identities.iter().map(|i| i.as_ref())) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(Error::Payload(format!(
+                "can't decrypt key data with identities from {}: {}",
+                identities.file, e
+            )));
+        }
+    };
+
+    let mut result = Vec::new();
+    if let Err(e) = reader.take(limit as u64 + 1).read_to_end(&mut result) {
+        return Err(Error::Payload(format!("can't decrypt key data: {}", e)));
+    }
+    if result.len() > limit {
+        return Err(Error::Validation(format!(
+            "decrypted payload exceeds the maximal size of {} bytes",
+            limit
+        )));
+    }
+
+    Ok(Cow::Owned(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use age::secrecy::ExposeSecret;
+    use mktemp::Temp;
+
+    fn identity_file(identity: &age::x25519::Identity) -> Temp {
+        let file = Temp::new_file().unwrap();
+        std::fs::write(&file, identity.to_string().expose_secret()).unwrap();
+        file
+    }
+
+    #[test]
+    fn decrypt_returns_encrypted_data() {
+        let identity = age::x25519::Identity::generate();
+        let file = identity_file(&identity);
+        let identities = Identities::from_file(file.to_str().unwrap()).unwrap();
+
+        let raw = b"{\"hostname\":[\"node01\"],\"keys\":[]}".to_vec();
+        let encrypted = encrypt(raw.clone(), &[identity.to_public().to_string()]).unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert_eq!(
+            decrypt(&encrypted, Some(&identities), 1000).unwrap(),
+            raw.as_slice()
+        );
+
+        // Unencrypted data doesn't require an identity
+        assert!(matches!(decrypt(&raw, None, 1000), Ok(Cow::Borrowed(_))));
+        assert!(matches!(
+            decrypt(&encrypted, None, 1000),
+            Err(Error::Payload(_))
+        ));
+    }
+
+    #[test]
+    fn decrypt_rejects_wrong_identity() {
+        let identity = age::x25519::Identity::generate();
+        let other = identity_file(&age::x25519::Identity::generate());
+        let identities = Identities::from_file(other.to_str().unwrap()).unwrap();
+
+        let encrypted = encrypt(b"{}".to_vec(), &[identity.to_public().to_string()]).unwrap();
+        assert!(matches!(
+            decrypt(&encrypted, Some(&identities), 1000),
+            Err(Error::Payload(_))
+        ));
+    }
+
+    #[test]
+    fn decrypt_stops_at_limit() {
+        let identity = age::x25519::Identity::generate();
+        let file = identity_file(&identity);
+        let identities = Identities::from_file(file.to_str().unwrap()).unwrap();
+
+        let encrypted = encrypt(vec![b'{'; 10000], &[identity.to_public().to_string()]).unwrap();
+        assert!(matches!(
+            decrypt(&encrypted, Some(&identities), 5000),
+            Err(Error::Validation(_))
+        ));
+        assert!(matches!(
+            decrypt(&encrypted, Some(&identities), 100),
+            Err(Error::Validation(_))
+        ));
+        assert_eq!(
+            decrypt(&encrypted, Some(&identities), 20000).unwrap().len(),
+            10000
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use crate::constants;
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    Backend(String),
+    Config(String),
+    Etcd(String),
+    Http(String),
+    Io(String),
+    Mqtt(String),
+    Payload(String),
+    Redis(String),
+    Validation(String),
+}
+
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Backend(_) => constants::EXIT_BACKEND_ERROR,
+            Error::Config(_) => constants::EXIT_CONFIG_ERROR,
+            Error::Etcd(_) => constants::EXIT_ETCD_ERROR,
+            Error::Http(_) => constants::EXIT_HTTP_ERROR,
+            Error::Io(_) => constants::EXIT_IO_ERROR,
+            Error::Mqtt(_) => constants::EXIT_MQTT_ERROR,
+            Error::Payload(_) => constants::EXIT_PAYLOAD_ERROR,
+            Error::Redis(_) => constants::EXIT_REDIS_ERROR,
+            Error::Validation(_) => constants::EXIT_VALIDATION_ERROR,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Backend(e)
+            | Error::Config(e)
+            | Error::Etcd(e)
+            | Error::Http(e)
+            | Error::Io(e)
+            | Error::Mqtt(e)
+            | Error::Payload(e)
+            | Error::Redis(e)
+            | Error::Validation(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e.to_string())
+    }
+}
+
+impl From<paho_mqtt::Error> for Error {
+    fn from(e: paho_mqtt::Error) -> Self {
+        Error::Mqtt(e.to_string())
+    }
+}
+
+impl From<redis::RedisError> for Error {
+    fn from(e: redis::RedisError) -> Self {
+        Error::Redis(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Payload(e.to_string())
+    }
+}
+
+impl From<std::string::FromUtf8Error> for Error {
+    fn from(e: std::string::FromUtf8Error) -> Self {
+        Error::Payload(e.to_string())
+    }
+}
+
+impl From<serde_yaml::Error> for Error {
+    fn from(e: serde_yaml::Error) -> Self {
+        Error::Config(e.to_string())
+    }
+}
+
+impl From<url::ParseError> for Error {
+    fn from(e: url::ParseError) -> Self {
+        Error::Validation(e.to_string())
+    }
+}
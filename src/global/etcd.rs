@@ -0,0 +1,722 @@
+use crate::constants;
+use crate::error::Error;
+use crate::transport::{Transport, Update};
+
+use base64::Engine;
+use log::{debug, info, warn};
+use serde::{Deserialize, Deserializer};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+use url::Url;
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Etcd {
+    pub endpoints: Vec<String>,
+    #[serde(default)]
+    pub lease_ttl: Option<u64>,
+    #[serde(default)]
+    pub password: String,
+    pub prefix: String,
+    #[serde(default = "etcd_default_progress_timeout")]
+    pub progress_timeout: u64,
+    #[serde(default = "etcd_default_timeout")]
+    pub timeout: u64,
+    #[serde(default)]
+    pub user: String,
+}
+
+fn etcd_default_timeout() -> u64 {
+    constants::DEFAULT_ETCD_TIMEOUT
+}
+
+fn etcd_default_progress_timeout() -> u64 {
+    constants::DEFAULT_ETCD_PROGRESS_TIMEOUT
+}
+
+pub fn validate(cfg: &Etcd) -> Result<(), Error> {
+    if cfg.endpoints.is_empty() {
+        return Err(Error::Config("no endpoints for etcd backend".to_string()));
+    }
+
+    for endpoint in cfg.endpoints.iter() {
+        match Url::parse(endpoint) {
+            Ok(v) if v.scheme() == "http" || v.scheme() == "https" => {}
+            Ok(_) => {
+                return Err(Error::Config(format!(
+                    "invalid etcd endpoint {}, scheme must be http or https",
+                    endpoint
+                )));
+            }
+            Err(e) => {
+                return Err(Error::Config(format!(
+                    "invalid etcd endpoint {}: {}",
+                    endpoint, e
+                )));
+            }
+        };
+    }
+
+    if cfg.prefix.is_empty() {
+        return Err(Error::Config("empty prefix for etcd backend".to_string()));
+    }
+
+    if cfg.lease_ttl == Some(0) {
+        return Err(Error::Config(
+            "invalid lease TTL for etcd backend".to_string(),
+        ));
+    }
+
+    if cfg.timeout == 0 {
+        return Err(Error::Config("invalid etcd timeout".to_string()));
+    }
+
+    if cfg.progress_timeout <= cfg.timeout {
+        return Err(Error::Config(
+            "etcd progress timeout must be larger than the timeout".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+// The JSON gateway of etcd encodes 64 bit integers as strings
+fn int64<'de, D>(deserializer: D) -> Result<i64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::String(v) => v.parse::<i64>().map_err(serde::de::Error::custom),
+        serde_json::Value::Number(v) => v
+            .as_i64()
+            .ok_or_else(|| serde::de::Error::custom("invalid 64 bit integer")),
+        _ => Err(serde::de::Error::custom("invalid 64 bit integer")),
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Header {
+    #[serde(default, deserialize_with = "int64")]
+    revision: i64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct KeyValue {
+    #[serde(default)]
+    key: String,
+    #[serde(default)]
+    value: String,
+    #[serde(default, deserialize_with = "int64")]
+    mod_revision: i64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RangeResponse {
+    #[serde(default)]
+    header: Header,
+    #[serde(default)]
+    kvs: Vec<KeyValue>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct LeaseGrantResponse {
+    #[serde(rename = "ID", deserialize_with = "int64")]
+    id: i64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct AuthenticateResponse {
+    #[serde(default)]
+    token: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Event {
+    #[serde(default, rename = "type")]
+    event_type: String,
+    #[serde(default)]
+    kv: KeyValue,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct WatchResult {
+    #[serde(default)]
+    created: bool,
+    #[serde(default)]
+    canceled: bool,
+    #[serde(default, deserialize_with = "int64")]
+    compact_revision: i64,
+    #[serde(default)]
+    cancel_reason: String,
+    #[serde(default)]
+    events: Vec<Event>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct WatchResponse {
+    result: Option<WatchResult>,
+    error: Option<serde_json::Value>,
+}
+
+fn encode(data: &[u8]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(data)
+}
+
+fn decode(data: &str) -> Result<Vec<u8>, Error> {
+    match base64::engine::general_purpose::STANDARD.decode(data) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(Error::Etcd(format!("invalid data from etcd: {}", e))),
+    }
+}
+
+// All keys starting with prefix: the range ends at the prefix with the last byte incremented
+fn range_end(prefix: &str) -> Vec<u8> {
+    let mut end = prefix.as_bytes().to_vec();
+    while let Some(last) = end.pop() {
+        if last < 0xff {
+            end.push(last + 1);
+            return end;
+        }
+    }
+    vec![0]
+}
+
+enum Notification {
+    Subscribed,
+    Changed(String, Vec<u8>, i64),
+    Compacted(i64),
+    Failed(String),
+}
+
+// Key data of each host is stored in <prefix><host>, optionally attached to a lease which expires
+// if the host doesn't publish its key data again within the lease TTL.
+// After a reconnect the watch continues at the last seen revision, so no change is lost. Only if
+// the revision was already compacted, all key data is read again
+pub struct EtcdTransport {
+    cfg: Etcd,
+    agent: ureq::Agent,
+    watch_agent: ureq::Agent,
+    token: Option<String>,
+    lease: Option<i64>,
+    notifications: Option<mpsc::Receiver<Notification>>,
+    stop: Arc<AtomicBool>,
+    revision: Option<i64>,
+    subscribed: bool,
+    connected: bool,
+    hosts: BTreeSet<String>,
+    pending: VecDeque<Update>,
+}
+
+impl EtcdTransport {
+    pub fn new(cfg: &Etcd) -> Self {
+        EtcdTransport {
+            cfg: cfg.clone(),
+            agent: ureq::AgentBuilder::new()
+                .timeout_connect(Duration::from_secs(cfg.timeout))
+                .build(),
+            // Reads of the watch stream time out, so the watch thread can check for the stop
+            // request and for missing progress notifications
+            watch_agent: ureq::AgentBuilder::new()
+                .timeout_connect(Duration::from_secs(cfg.timeout))
+                .timeout_read(Duration::from_secs(cfg.timeout))
+                .build(),
+            token: None,
+            lease: None,
+            notifications: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            revision: None,
+            subscribed: false,
+            connected: false,
+            hosts: BTreeSet::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn key(&self, host: &str) -> Result<String, Error> {
+        if host.is_empty() || host.contains('/') {
+            return Err(Error::Validation(format!("invalid hostname {}", host)));
+        }
+        Ok(format!("{}{}", self.cfg.prefix, host))
+    }
+
+    fn host(&self, key: &str) -> Result<String, Error> {
+        let key = String::from_utf8(decode(key)?)?;
+        match key.strip_prefix(&self.cfg.prefix) {
+            Some(v) if !v.is_empty() => Ok(v.to_string()),
+            _ => Err(Error::Etcd(format!(
+                "key {} doesn't belong to prefix {}",
+                key, self.cfg.prefix
+            ))),
+        }
+    }
+
+    fn authenticate(&mut self, endpoint: &str) -> Result<(), Error> {
+        if self.cfg.user.is_empty() || self.token.is_some() {
+            return Ok(());
+        }
+
+        debug!("authenticating as {} on {}", self.cfg.user, endpoint);
+        let response = self
+            .agent
+            .post(&format!("{}/v3/auth/authenticate", endpoint))
+            .timeout(Duration::from_secs(self.cfg.timeout))
+            .send_json(serde_json::json!({
+                "name": self.cfg.user,
+                "password": self.cfg.password,
+            }));
+        let auth: AuthenticateResponse = match response {
+            Ok(v) => v.into_json()?,
+            Err(e) => {
+                return Err(Error::Etcd(format!(
+                    "authentication on {} failed: {}",
+                    endpoint, e
+                )));
+            }
+        };
+        self.token = Some(auth.token);
+        Ok(())
+    }
+
+    // Endpoints are tried in order until one of them answers
+    fn request<T: serde::de::DeserializeOwned>(
+        &mut self,
+        path: &str,
+        body: serde_json::Value,
+    ) -> Result<T, Error> {
+        let mut last_error = Error::Etcd("no etcd endpoint available".to_string());
+
+        for endpoint in self.cfg.endpoints.clone().iter() {
+            if let Err(e) = self.authenticate(endpoint) {
+                last_error = e;
+                continue;
+            }
+
+            let mut request = self
+                .agent
+                .post(&format!("{}{}", endpoint, path))
+                .timeout(Duration::from_secs(self.cfg.timeout));
+            if let Some(token) = &self.token {
+                request = request.set("Authorization", token);
+            }
+
+            match request.send_json(&body) {
+                Ok(v) => return Ok(v.into_json()?),
+                Err(ureq::Error::Status(401, _)) => {
+                    // Tokens expire, authenticate again on the next request
+                    self.token = None;
+                    last_error = Error::Etcd(format!("{} rejected authentication", endpoint));
+                }
+                Err(ureq::Error::Status(code, response)) => {
+                    // Rejected requests would be rejected by all endpoints
+                    return Err(Error::Etcd(format!(
+                        "{} failed with status {}: {}",
+                        path,
+                        code,
+                        response.into_string().unwrap_or_default()
+                    )));
+                }
+                Err(e) => {
+                    warn!("request to {} failed: {}", endpoint, e);
+                    last_error = Error::Etcd(format!("request to {} failed: {}", endpoint, e));
+                }
+            };
+        }
+
+        Err(last_error)
+    }
+
+    fn fetch(&mut self) -> Result<(BTreeMap<String, Vec<u8>>, i64), Error> {
+        let mut result: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+        let response: RangeResponse = self.request(
+            "/v3/kv/range",
+            serde_json::json!({
+                "key": encode(self.cfg.prefix.as_bytes()),
+                "range_end": encode(&range_end(&self.cfg.prefix)),
+            }),
+        )?;
+
+        for kv in response.kvs.iter() {
+            match self.host(&kv.key) {
+                Ok(host) => {
+                    result.insert(host, decode(&kv.value)?);
+                }
+                Err(e) => warn!("{}", e),
+            };
+        }
+
+        Ok((result, response.header.revision))
+    }
+
+    fn grant_lease(&mut self, ttl: u64) -> Result<i64, Error> {
+        if let Some(v) = self.lease {
+            return Ok(v);
+        }
+
+        let response: LeaseGrantResponse =
+            self.request("/v3/lease/grant", serde_json::json!({ "TTL": ttl }))?;
+        debug!("granted lease {:x} with TTL {}", response.id, ttl);
+        self.lease = Some(response.id);
+        Ok(response.id)
+    }
+
+    // The watch is created by the first endpoint accepting it. Each attempt uses its own channel,
+    // so a late answer of a failed attempt can't be mistaken for the answer of the next endpoint
+    fn start_watch(&mut self, revision: i64) -> Result<(), Error> {
+        let body = serde_json::json!({
+            "create_request": {
+                "key": encode(self.cfg.prefix.as_bytes()),
+                "range_end": encode(&range_end(&self.cfg.prefix)),
+                "start_revision": revision.to_string(),
+                "progress_notify": true,
+            }
+        });
+
+        let mut last_error = Error::Etcd("no etcd endpoint available".to_string());
+        for endpoint in self.cfg.endpoints.clone().iter() {
+            if let Err(e) = self.authenticate(endpoint) {
+                last_error = e;
+                continue;
+            }
+
+            let mut request = self.watch_agent.post(&format!("{}/v3/watch", endpoint));
+            if let Some(token) = &self.token {
+                request = request.set("Authorization", token);
+            }
+
+            let response = match request.send_json(&body) {
+                Ok(v) => v,
+                Err(e) => {
+                    if let ureq::Error::Status(401, _) = e {
+                        self.token = None;
+                    }
+                    warn!("watch on {} failed: {}", endpoint, e);
+                    last_error = Error::Etcd(format!("watch on {} failed: {}", endpoint, e));
+                    continue;
+                }
+            };
+
+            let (sender, receiver) = mpsc::channel::<Notification>();
+            self.stop = Arc::new(AtomicBool::new(false));
+            let reader = WatchReader {
+                reader: response.into_reader(),
+                stop: self.stop.clone(),
+                progress_timeout: Duration::from_secs(self.cfg.progress_timeout),
+                last_read: Instant::now(),
+            };
+            thread::spawn(move || watch(reader, sender));
+
+            match receiver.recv_timeout(Duration::from_secs(self.cfg.timeout)) {
+                Ok(Notification::Subscribed) => {
+                    info!(
+                        "watching prefix {} on {} from revision {}",
+                        self.cfg.prefix, endpoint, revision
+                    );
+                    self.notifications = Some(receiver);
+                    return Ok(());
+                }
+                Ok(Notification::Compacted(v)) => {
+                    return Err(Error::Etcd(format!(
+                        "revision {} was already compacted, revision {} is the oldest",
+                        revision, v
+                    )));
+                }
+                Ok(Notification::Failed(e)) => {
+                    last_error = Error::Etcd(format!("watch on {} failed: {}", endpoint, e));
+                }
+                _ => {
+                    last_error = Error::Etcd(format!("watch on {} timed out", endpoint));
+                }
+            };
+            self.stop_watch();
+        }
+
+        Err(last_error)
+    }
+
+    fn stop_watch(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.notifications = None;
+    }
+
+    fn resync(&mut self) -> Result<(), Error> {
+        self.stop_watch();
+
+        if let Some(revision) = self.revision {
+            match self.start_watch(revision + 1) {
+                Ok(_) => return Ok(()),
+                Err(e) => {
+                    info!("{}, reading all key data again", e);
+                    self.revision = None;
+                }
+            };
+        }
+
+        let (data, revision) = self.fetch()?;
+        info!(
+            "read key data of {} hosts from {} at revision {}",
+            data.len(),
+            self.location(),
+            revision
+        );
+
+        self.pending.clear();
+        for host in self.hosts.iter() {
+            if !data.contains_key(host) {
+                self.pending
+                    .push_back(Update::Data(host.clone(), Vec::new()));
+            }
+        }
+        self.hosts = data.keys().cloned().collect();
+        for (host, payload) in data.into_iter() {
+            self.pending.push_back(Update::Data(host, payload));
+        }
+
+        self.start_watch(revision + 1)?;
+        self.revision = Some(revision);
+        Ok(())
+    }
+
+    fn disconnect(&mut self) {
+        self.stop_watch();
+        self.connected = false;
+    }
+}
+
+// etcd sends progress notifications on idle watches, so a watch stream without any data within
+// the progress timeout is considered lost (e.g. a dead endpoint without a TCP reset)
+struct WatchReader {
+    reader: Box<dyn Read + Send + Sync>,
+    stop: Arc<AtomicBool>,
+    progress_timeout: Duration,
+    last_read: Instant,
+}
+
+impl Read for WatchReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.stop.load(Ordering::Relaxed) {
+                return Err(io::Error::other("watch was stopped"));
+            }
+
+            match self.reader.read(buf) {
+                Ok(v) => {
+                    self.last_read = Instant::now();
+                    return Ok(v);
+                }
+                Err(e)
+                    if e.kind() == io::ErrorKind::TimedOut
+                        || e.kind() == io::ErrorKind::WouldBlock =>
+                {
+                    if self.last_read.elapsed() > self.progress_timeout {
+                        return Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            format!(
+                                "no progress notification received within {} seconds",
+                                self.progress_timeout.as_secs()
+                            ),
+                        ));
+                    }
+                }
+                Err(e) => return Err(e),
+            };
+        }
+    }
+}
+
+// Reads the stream of watch responses. The thread ends with the stream, on a stop request or as
+// soon as the receiver was dropped and the next response arrives
+fn watch(reader: WatchReader, sender: mpsc::Sender<Notification>) {
+    let stream = serde_json::Deserializer::from_reader(reader).into_iter::<WatchResponse>();
+
+    for response in stream {
+        let result = match response {
+            Ok(WatchResponse {
+                result: Some(v), ..
+            }) => v,
+            Ok(WatchResponse { error: Some(e), .. }) => {
+                let _ = sender.send(Notification::Failed(e.to_string()));
+                return;
+            }
+            Ok(_) => continue,
+            Err(e) => {
+                let _ = sender.send(Notification::Failed(e.to_string()));
+                return;
+            }
+        };
+
+        if result.compact_revision > 0 {
+            let _ = sender.send(Notification::Compacted(result.compact_revision));
+            return;
+        }
+        if result.canceled {
+            let _ = sender.send(Notification::Failed(format!(
+                "watch was canceled: {}",
+                result.cancel_reason
+            )));
+            return;
+        }
+        if result.created && sender.send(Notification::Subscribed).is_err() {
+            return;
+        }
+
+        for event in result.events.into_iter() {
+            let payload = if event.event_type == "DELETE" {
+                Vec::new()
+            } else {
+                match decode(&event.kv.value) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        warn!("{}", e);
+                        continue;
+                    }
+                }
+            };
+            let notification = Notification::Changed(event.kv.key, payload, event.kv.mod_revision);
+            if sender.send(notification).is_err() {
+                return;
+            }
+        }
+    }
+
+    let _ = sender.send(Notification::Failed("watch stream ended".to_string()));
+}
+
+impl Transport for EtcdTransport {
+    fn location(&self) -> String {
+        format!(
+            "etcd prefix {} on {}",
+            self.cfg.prefix,
+            self.cfg.endpoints.join(",")
+        )
+    }
+
+    fn publish(&mut self, data: &BTreeMap<String, Vec<u8>>) -> Result<(), Error> {
+        for (host, payload) in data.iter() {
+            if payload.is_empty() {
+                self.delete(std::slice::from_ref(host))?;
+                continue;
+            }
+
+            let key = self.key(host)?;
+            let mut body = serde_json::json!({
+                "key": encode(key.as_bytes()),
+                "value": encode(payload),
+            });
+            if let Some(ttl) = self.cfg.lease_ttl {
+                body["lease"] = serde_json::json!(self.grant_lease(ttl)?.to_string());
+            }
+            debug!(host = host; "putting key data of {} to {}", host, key);
+            let _: serde_json::Value = self.request("/v3/kv/put", body)?;
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, hosts: &[String]) -> Result<(), Error> {
+        for host in hosts.iter() {
+            let key = self.key(host)?;
+            debug!(host = host; "deleting {}", key);
+            let _: serde_json::Value = self.request(
+                "/v3/kv/deleterange",
+                serde_json::json!({ "key": encode(key.as_bytes()) }),
+            )?;
+        }
+        Ok(())
+    }
+
+    // All data is read at once, there is no need to wait for further data
+    fn list(&mut self, _wait: Duration) -> Result<BTreeMap<String, Vec<u8>>, Error> {
+        let (data, _) = self.fetch()?;
+        Ok(data)
+    }
+
+    fn subscribe(&mut self) -> Result<(), Error> {
+        info!("subscribing to key data on {}", self.location());
+        self.subscribed = true;
+        self.connected = false;
+        self.revision = None;
+        self.hosts.clear();
+        self.pending.clear();
+        Ok(())
+    }
+
+    fn poll(&mut self, timeout: Duration) -> Result<Option<Update>, Error> {
+        if !self.subscribed {
+            return Err(Error::Etcd(format!(
+                "not subscribed to {}",
+                self.location()
+            )));
+        }
+
+        if !self.connected {
+            if let Err(e) = self.resync() {
+                warn!("{}, retrying", e);
+                self.disconnect();
+                thread::sleep(timeout);
+                return Ok(None);
+            }
+            self.connected = true;
+            return Ok(Some(Update::Connected));
+        }
+
+        if let Some(v) = self.pending.pop_front() {
+            return Ok(Some(v));
+        }
+
+        let notification = match &self.notifications {
+            Some(v) => v.recv_timeout(timeout),
+            None => Err(mpsc::RecvTimeoutError::Disconnected),
+        };
+
+        match notification {
+            Ok(Notification::Changed(key, payload, revision)) => {
+                self.revision = Some(revision);
+                let host = match self.host(&key) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        warn!("{}", e);
+                        return Ok(None);
+                    }
+                };
+                if payload.is_empty() {
+                    self.hosts.remove(&host);
+                } else {
+                    self.hosts.insert(host.clone());
+                }
+                Ok(Some(Update::Data(host, payload)))
+            }
+            Ok(Notification::Subscribed) => Ok(None),
+            Ok(Notification::Compacted(v)) => {
+                warn!(
+                    "watched revision was compacted, revision {} is the oldest, reading all key data again",
+                    v
+                );
+                self.revision = None;
+                self.disconnect();
+                Ok(Some(Update::Disconnected))
+            }
+            Ok(Notification::Failed(e)) => {
+                warn!("watch on {} failed: {}, reconnecting", self.location(), e);
+                self.disconnect();
+                Ok(Some(Update::Disconnected))
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => Ok(None),
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                warn!("watch on {} was lost, reconnecting", self.location());
+                self.disconnect();
+                Ok(Some(Update::Disconnected))
+            }
+        }
+    }
+
+    fn close(&mut self) {
+        self.stop_watch();
+        self.subscribed = false;
+        self.connected = false;
+        self.revision = None;
+        self.hosts.clear();
+        self.pending.clear();
+    }
+}
@@ -0,0 +1,319 @@
+use crate::constants;
+use crate::error::Error;
+use crate::transport::{Transport, Update};
+
+use inotify::{Inotify, WatchMask};
+use log::{debug, info, warn};
+use serde::Deserialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Filesystem {
+    pub directory: String,
+    #[serde(default = "filesystem_default_poll_interval")]
+    pub poll_interval: u64,
+}
+
+fn filesystem_default_poll_interval() -> u64 {
+    constants::DEFAULT_FILESYSTEM_POLL_INTERVAL
+}
+
+pub fn validate(cfg: &Filesystem) -> Result<(), Error> {
+    if cfg.directory.is_empty() {
+        return Err(Error::Config(
+            "empty directory for filesystem backend".to_string(),
+        ));
+    }
+
+    if cfg.poll_interval == 0 {
+        return Err(Error::Config(
+            "invalid poll interval for filesystem backend".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+// Key data of each host is stored in <directory>/<host>.json. inotify only triggers an
+// early scan of the directory, because changes on other clients of network file systems
+// like NFS or Lustre are not reported by inotify
+pub struct FilesystemTransport {
+    cfg: Filesystem,
+    inotify: Option<Inotify>,
+    subscribed: bool,
+    connected: bool,
+    last_scan: Instant,
+    state: BTreeMap<String, (SystemTime, Vec<u8>)>,
+    pending: VecDeque<Update>,
+}
+
+impl FilesystemTransport {
+    pub fn new(cfg: &Filesystem) -> Self {
+        FilesystemTransport {
+            cfg: cfg.clone(),
+            inotify: None,
+            subscribed: false,
+            connected: false,
+            last_scan: Instant::now(),
+            state: BTreeMap::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn file(&self, host: &str) -> Result<String, Error> {
+        if host.is_empty() || host.starts_with('.') || host.contains('/') {
+            return Err(Error::Validation(format!("invalid hostname {}", host)));
+        }
+        Ok(format!("{}/{}.json", self.cfg.directory, host))
+    }
+
+    fn scan(&self) -> Result<BTreeMap<String, SystemTime>, Error> {
+        let mut result: BTreeMap<String, SystemTime> = BTreeMap::new();
+        let dir = match fs::read_dir(Path::new(&self.cfg.directory)) {
+            Ok(v) => v,
+            Err(e) => {
+                return Err(Error::Io(format!(
+                    "can't read directory {}: {}",
+                    self.cfg.directory, e
+                )));
+            }
+        };
+
+        for entry in dir.flatten() {
+            let name = match entry.file_name().into_string() {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+            let host = match name.strip_suffix(".json") {
+                Some(v) if !v.is_empty() && !v.starts_with('.') => v.to_string(),
+                _ => continue,
+            };
+            if let Ok(meta) = entry.metadata() {
+                if meta.is_file() {
+                    result.insert(host, meta.modified().unwrap_or(SystemTime::UNIX_EPOCH));
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn read(&self, host: &str) -> Option<Vec<u8>> {
+        let file = self.file(host).ok()?;
+        match fs::read(&file) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                warn!(host = host; "can't read {}: {}", file, e);
+                None
+            }
+        }
+    }
+
+    // Compare the directory with the last scan and queue all changes
+    fn update(&mut self) -> Result<(), Error> {
+        debug!("scanning {} for changes", self.cfg.directory);
+        let current = self.scan()?;
+        self.last_scan = Instant::now();
+
+        let removed: Vec<String> = self
+            .state
+            .keys()
+            .filter(|h| !current.contains_key(*h))
+            .cloned()
+            .collect();
+        for host in removed.into_iter() {
+            self.state.remove(&host);
+            self.pending.push_back(Update::Data(host, Vec::new()));
+        }
+
+        for (host, modified) in current.into_iter() {
+            if let Some((m, _)) = self.state.get(&host) {
+                if *m == modified {
+                    continue;
+                }
+            }
+
+            let payload = match self.read(&host) {
+                Some(v) => v,
+                None => continue,
+            };
+            let changed = match self.state.get(&host) {
+                Some((_, p)) => *p != payload,
+                None => true,
+            };
+            self.state.insert(host.clone(), (modified, payload.clone()));
+            if changed {
+                self.pending.push_back(Update::Data(host, payload));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn changes_reported(&mut self) -> bool {
+        let mut buffer = [0; 4096];
+        let inotify = match self.inotify.as_mut() {
+            Some(v) => v,
+            None => return false,
+        };
+
+        match inotify.read_events(&mut buffer) {
+            Ok(events) => events.count() > 0,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => false,
+            Err(e) => {
+                warn!(
+                    "reading inotify events failed, falling back to polling: {}",
+                    e
+                );
+                self.inotify = None;
+                false
+            }
+        }
+    }
+}
+
+impl Transport for FilesystemTransport {
+    fn location(&self) -> String {
+        format!("directory {}", self.cfg.directory)
+    }
+
+    // Files are written to a temporary file in the same directory and renamed, so readers never
+    // see partial data
+    fn publish(&mut self, data: &BTreeMap<String, Vec<u8>>) -> Result<(), Error> {
+        for (host, payload) in data.iter() {
+            if payload.is_empty() {
+                self.delete(std::slice::from_ref(host))?;
+                continue;
+            }
+
+            let file = self.file(host)?;
+            let tempfile = format!(
+                "{}/.{}.json.{}",
+                self.cfg.directory,
+                host,
+                std::process::id()
+            );
+            info!(host = host; "writing key data of {} to {}", host, file);
+            if let Err(e) = fs::write(&tempfile, payload) {
+                return Err(Error::Io(format!("can't write {}: {}", tempfile, e)));
+            }
+            if let Err(e) = fs::rename(&tempfile, &file) {
+                let _ = fs::remove_file(&tempfile);
+                return Err(Error::Io(format!(
+                    "can't rename {} to {}: {}",
+                    tempfile, file, e
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, hosts: &[String]) -> Result<(), Error> {
+        for host in hosts.iter() {
+            let file = self.file(host)?;
+            info!(host = host; "removing {}", file);
+            match fs::remove_file(&file) {
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(Error::Io(format!("can't remove {}: {}", file, e)));
+                }
+            };
+        }
+        Ok(())
+    }
+
+    fn list(&mut self, _wait: Duration) -> Result<BTreeMap<String, Vec<u8>>, Error> {
+        let mut result: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+        for host in self.scan()?.into_keys() {
+            if let Some(payload) = self.read(&host) {
+                result.insert(host, payload);
+            }
+        }
+        Ok(result)
+    }
+
+    fn subscribe(&mut self) -> Result<(), Error> {
+        let mask = WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::DELETE;
+        self.inotify = match Inotify::init() {
+            Ok(v) => match v.watches().add(&self.cfg.directory, mask) {
+                Ok(_) => Some(v),
+                Err(e) => {
+                    warn!(
+                        "can't watch {} with inotify, falling back to polling: {}",
+                        self.cfg.directory, e
+                    );
+                    None
+                }
+            },
+            Err(e) => {
+                warn!("can't initialise inotify, falling back to polling: {}", e);
+                None
+            }
+        };
+
+        self.scan()?;
+        info!(
+            "watching {} for key data, scanning every {} seconds",
+            self.cfg.directory, self.cfg.poll_interval
+        );
+        self.state.clear();
+        self.pending.clear();
+        self.subscribed = true;
+        self.connected = false;
+        Ok(())
+    }
+
+    fn poll(&mut self, timeout: Duration) -> Result<Option<Update>, Error> {
+        if !self.subscribed {
+            return Err(Error::Io(format!(
+                "not subscribed to {}",
+                self.cfg.directory
+            )));
+        }
+
+        if !self.connected {
+            if let Err(e) = self.update() {
+                warn!("{}, retrying", e);
+                thread::sleep(timeout);
+                return Ok(None);
+            }
+            self.connected = true;
+            return Ok(Some(Update::Connected));
+        }
+
+        if let Some(v) = self.pending.pop_front() {
+            return Ok(Some(v));
+        }
+
+        let start = Instant::now();
+        let poll_interval = Duration::from_secs(self.cfg.poll_interval);
+        let check = Duration::from_millis(constants::FILESYSTEM_INOTIFY_CHECK_MS);
+        while start.elapsed() < timeout {
+            if self.last_scan.elapsed() >= poll_interval || self.changes_reported() {
+                if let Err(e) = self.update() {
+                    warn!("{}", e);
+                    self.connected = false;
+                    return Ok(Some(Update::Disconnected));
+                }
+                return Ok(self.pending.pop_front());
+            }
+            thread::sleep(check.min(timeout.saturating_sub(start.elapsed())));
+        }
+
+        Ok(None)
+    }
+
+    fn close(&mut self) {
+        self.inotify = None;
+        self.subscribed = false;
+        self.connected = false;
+        self.state.clear();
+        self.pending.clear();
+    }
+}
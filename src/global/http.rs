@@ -0,0 +1,479 @@
+use crate::constants;
+use crate::error::Error;
+use crate::transport::{Transport, Update};
+
+use base64::Engine;
+use log::{debug, info, warn};
+use serde::Deserialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::thread;
+use std::time::{Duration, Instant};
+use url::Url;
+
+type Snapshot = (BTreeMap<String, Vec<u8>>, Option<String>);
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Http {
+    #[serde(default = "http_default_interval")]
+    pub interval: u64,
+    #[serde(default)]
+    pub max_removal: Option<u64>,
+    #[serde(default)]
+    pub password: String,
+    #[serde(default)]
+    pub proxy: String,
+    #[serde(default = "http_default_timeout")]
+    pub timeout: u64,
+    pub url: String,
+    #[serde(default)]
+    pub user: String,
+}
+
+fn http_default_interval() -> u64 {
+    constants::DEFAULT_HTTP_INTERVAL
+}
+
+fn http_default_timeout() -> u64 {
+    constants::DEFAULT_HTTP_TIMEOUT
+}
+
+pub fn validate(cfg: &Http) -> Result<(), Error> {
+    match Url::parse(&cfg.url) {
+        Ok(v) if v.scheme() == "http" || v.scheme() == "https" => {}
+        Ok(_) => {
+            return Err(Error::Config(format!(
+                "invalid URL {}, scheme must be http or https",
+                cfg.url
+            )));
+        }
+        Err(e) => {
+            return Err(Error::Config(format!("invalid URL {}: {}", cfg.url, e)));
+        }
+    };
+
+    if !cfg.proxy.is_empty() {
+        if let Err(e) = ureq::Proxy::new(&cfg.proxy) {
+            return Err(Error::Config(format!("invalid proxy {}: {}", cfg.proxy, e)));
+        }
+    }
+
+    if cfg.interval == 0 {
+        return Err(Error::Config("invalid HTTP poll interval".to_string()));
+    }
+
+    if let Some(v) = cfg.max_removal {
+        if v == 0 || v > 100 {
+            return Err(Error::Config(format!(
+                "invalid maximal removal of {} percent, must be between 1 and 100",
+                v
+            )));
+        }
+    }
+
+    if cfg.timeout == 0 {
+        return Err(Error::Config("invalid HTTP timeout".to_string()));
+    }
+
+    Ok(())
+}
+
+// Reads snapshots of the key data of all hosts from a HTTP(S) URL. The snapshot is a JSON object,
+// indexed by host, with the key data of each host as value (the export format of ssh-key-backup).
+// Unchanged snapshots are detected by their ETag. Publishing isn't possible, the key data is
+// maintained by the server
+pub struct HttpTransport {
+    cfg: Http,
+    agent: ureq::Agent,
+    etag: Option<String>,
+    last_fetch: Option<Instant>,
+    subscribed: bool,
+    connected: bool,
+    state: BTreeMap<String, Vec<u8>>,
+    pending: VecDeque<Update>,
+}
+
+impl HttpTransport {
+    pub fn new(cfg: &Http) -> Self {
+        let mut agent = ureq::AgentBuilder::new()
+            .timeout_connect(Duration::from_secs(cfg.timeout))
+            .timeout(Duration::from_secs(cfg.timeout))
+            .try_proxy_from_env(true);
+        if !cfg.proxy.is_empty() {
+            // The proxy was validated with the configuration
+            if let Ok(v) = ureq::Proxy::new(&cfg.proxy) {
+                agent = agent.proxy(v);
+            }
+        }
+
+        HttpTransport {
+            cfg: cfg.clone(),
+            agent: agent.build(),
+            etag: None,
+            last_fetch: None,
+            subscribed: false,
+            connected: false,
+            state: BTreeMap::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    // Returns the snapshot and its ETag, or None if the snapshot still matches etag
+    fn fetch(&self, etag: Option<&str>) -> Result<Option<Snapshot>, Error> {
+        let mut request = self.agent.get(&self.cfg.url);
+        if !self.cfg.user.is_empty() {
+            let credentials = base64::engine::general_purpose::STANDARD
+                .encode(format!("{}:{}", self.cfg.user, self.cfg.password));
+            request = request.set("Authorization", &format!("Basic {}", credentials));
+        }
+        if let Some(v) = etag {
+            request = request.set("If-None-Match", v);
+        }
+
+        debug!("requesting key data from {}", self.cfg.url);
+        let response = match request.call() {
+            Ok(v) => v,
+            Err(e) => {
+                return Err(Error::Http(format!(
+                    "can't fetch key data from {}: {}",
+                    self.cfg.url, e
+                )));
+            }
+        };
+        if response.status() == 304 {
+            debug!("key data on {} has not changed", self.cfg.url);
+            return Ok(None);
+        }
+        if response.status() != 200 {
+            return Err(Error::Http(format!(
+                "can't fetch key data from {}: unexpected status {}",
+                self.cfg.url,
+                response.status()
+            )));
+        }
+
+        let new_etag = response.header("ETag").map(|v| v.to_string());
+        let snapshot: BTreeMap<String, serde_json::Value> = match response.into_json() {
+            Ok(v) => v,
+            Err(e) => {
+                return Err(Error::Payload(format!(
+                    "can't parse key data from {}: {}",
+                    self.cfg.url, e
+                )));
+            }
+        };
+
+        let mut result: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+        for (host, msg) in snapshot.into_iter() {
+            result.insert(host, serde_json::to_vec(&msg)?);
+        }
+
+        Ok(Some((result, new_etag)))
+    }
+
+    // Compare the snapshot with the previous snapshot and queue all changes
+    fn update(&mut self) -> Result<(), Error> {
+        self.last_fetch = Some(Instant::now());
+        let (snapshot, etag) = match self.fetch(self.etag.as_deref())? {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        info!(
+            "received key data of {} hosts from {}",
+            snapshot.len(),
+            self.cfg.url
+        );
+
+        // The previous ETag is kept for rejected snapshots, so they are evaluated again
+        let changes = match diff(&self.state, &snapshot, self.cfg.max_removal) {
+            Ok(v) => v,
+            Err(e) => {
+                return Err(Error::Validation(format!(
+                    "rejecting snapshot from {}, keeping current key data: {}",
+                    self.cfg.url, e
+                )));
+            }
+        };
+        self.pending.extend(changes);
+        self.state = snapshot;
+        self.etag = etag;
+        Ok(())
+    }
+}
+
+// Changes from the previous snapshot. An empty or truncated snapshot would remove the key data of
+// all missing hosts, so a snapshot removing all hosts, or more than max_removal percent of them,
+// is rejected
+fn diff(
+    state: &BTreeMap<String, Vec<u8>>,
+    snapshot: &BTreeMap<String, Vec<u8>>,
+    max_removal: Option<u64>,
+) -> Result<Vec<Update>, Error> {
+    let removed: Vec<&String> = state
+        .keys()
+        .filter(|host| !snapshot.contains_key(*host))
+        .collect();
+
+    if !state.is_empty() {
+        if removed.len() == state.len() {
+            return Err(Error::Validation(format!(
+                "snapshot would remove all {} hosts",
+                state.len()
+            )));
+        }
+        if let Some(v) = max_removal {
+            if removed.len() as u64 * 100 > v * state.len() as u64 {
+                return Err(Error::Validation(format!(
+                    "snapshot would remove {} of {} hosts, more than {} percent",
+                    removed.len(),
+                    state.len(),
+                    v
+                )));
+            }
+        }
+    }
+
+    let mut result: Vec<Update> = removed
+        .into_iter()
+        .map(|host| Update::Data(host.clone(), Vec::new()))
+        .collect();
+    for (host, payload) in snapshot.iter() {
+        if state.get(host) != Some(payload) {
+            result.push(Update::Data(host.clone(), payload.clone()));
+        }
+    }
+    Ok(result)
+}
+
+impl Transport for HttpTransport {
+    fn location(&self) -> String {
+        format!("URL {}", self.cfg.url)
+    }
+
+    fn publish(&mut self, _data: &BTreeMap<String, Vec<u8>>) -> Result<(), Error> {
+        Err(Error::Config(format!(
+            "key data from {} is read-only",
+            self.cfg.url
+        )))
+    }
+
+    fn delete(&mut self, _hosts: &[String]) -> Result<(), Error> {
+        Err(Error::Config(format!(
+            "key data from {} is read-only",
+            self.cfg.url
+        )))
+    }
+
+    // The snapshot contains all data, there is no need to wait for further data
+    fn list(&mut self, _wait: Duration) -> Result<BTreeMap<String, Vec<u8>>, Error> {
+        match self.fetch(None)? {
+            Some((v, _)) => Ok(v),
+            None => Ok(BTreeMap::new()),
+        }
+    }
+
+    fn subscribe(&mut self) -> Result<(), Error> {
+        info!(
+            "fetching key data from {} every {} seconds",
+            self.cfg.url, self.cfg.interval
+        );
+        self.subscribed = true;
+        self.connected = false;
+        self.etag = None;
+        self.last_fetch = None;
+        self.state.clear();
+        self.pending.clear();
+        Ok(())
+    }
+
+    fn poll(&mut self, timeout: Duration) -> Result<Option<Update>, Error> {
+        if !self.subscribed {
+            return Err(Error::Http(format!("not subscribed to {}", self.cfg.url)));
+        }
+
+        // Failed requests are retried after the poll interval
+        let interval = Duration::from_secs(self.cfg.interval);
+        let next = match self.last_fetch {
+            Some(v) => interval.saturating_sub(v.elapsed()),
+            None => Duration::ZERO,
+        };
+
+        if !self.connected {
+            if next > timeout {
+                thread::sleep(timeout);
+                return Ok(None);
+            }
+            thread::sleep(next);
+            if let Err(e) = self.update() {
+                warn!("{}, retrying in {} seconds", e, self.cfg.interval);
+                return Ok(None);
+            }
+            self.connected = true;
+            return Ok(Some(Update::Connected));
+        }
+
+        if let Some(v) = self.pending.pop_front() {
+            return Ok(Some(v));
+        }
+
+        if next > timeout {
+            thread::sleep(timeout);
+            return Ok(None);
+        }
+
+        thread::sleep(next);
+        if let Err(e) = self.update() {
+            warn!("{}", e);
+            self.connected = false;
+            return Ok(Some(Update::Disconnected));
+        }
+        Ok(self.pending.pop_front())
+    }
+
+    fn close(&mut self) {
+        self.subscribed = false;
+        self.connected = false;
+        self.etag = None;
+        self.state.clear();
+        self.pending.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    fn state(hosts: &[(&str, &str)]) -> BTreeMap<String, Vec<u8>> {
+        hosts
+            .iter()
+            .map(|(h, p)| (h.to_string(), p.as_bytes().to_vec()))
+            .collect()
+    }
+
+    // Answers one request per response and reports the If-None-Match header of each request
+    fn serve(responses: Vec<String>) -> (String, mpsc::Receiver<Option<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/keys", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut etag = None;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("if-none-match") {
+                            etag = Some(value.trim().to_string());
+                        }
+                    }
+                }
+                tx.send(etag).unwrap();
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        (url, rx)
+    }
+
+    fn transport(url: &str) -> HttpTransport {
+        let cfg: Http = serde_yaml::from_str(&format!("url: {}\ntimeout: 5\n", url)).unwrap();
+        HttpTransport::new(&cfg)
+    }
+
+    #[test]
+    fn diff_queues_added_changed_and_removed_hosts() {
+        let old = state(&[("a", "1"), ("b", "2"), ("c", "3")]);
+        let new = state(&[("a", "1"), ("b", "4"), ("d", "5")]);
+        let changes = diff(&old, &new, None).unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                Update::Data("c".to_string(), Vec::new()),
+                Update::Data("b".to_string(), b"4".to_vec()),
+                Update::Data("d".to_string(), b"5".to_vec()),
+            ]
+        );
+
+        assert!(diff(&new, &new, None).unwrap().is_empty());
+        assert!(diff(&BTreeMap::new(), &BTreeMap::new(), None)
+            .unwrap()
+            .is_empty());
+        assert_eq!(diff(&BTreeMap::new(), &new, None).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn diff_rejects_removal_of_all_hosts() {
+        let old = state(&[("a", "1"), ("b", "2")]);
+        assert!(matches!(
+            diff(&old, &BTreeMap::new(), None),
+            Err(Error::Validation(_))
+        ));
+        assert!(matches!(
+            diff(&old, &state(&[("c", "3")]), Some(100)),
+            Err(Error::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn diff_rejects_removal_above_max_removal() {
+        let old = state(&[("a", "1"), ("b", "2"), ("c", "3"), ("d", "4")]);
+        let new = state(&[("a", "1"), ("b", "2")]);
+        assert_eq!(diff(&old, &new, Some(50)).unwrap().len(), 2);
+        assert!(matches!(
+            diff(&old, &new, Some(49)),
+            Err(Error::Validation(_))
+        ));
+        assert_eq!(diff(&old, &new, None).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn update_keeps_state_of_rejected_snapshot() {
+        let (url, _rx) = serve(vec![
+            "HTTP/1.1 200 OK\r\nETag: \"1\"\r\nContent-Length: 17\r\nConnection: close\r\n\r\n{\"a\":{},\"b\":{}}\r\n".to_string(),
+            "HTTP/1.1 200 OK\r\nETag: \"2\"\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}".to_string(),
+        ]);
+        let mut transport = transport(&url);
+        transport.update().unwrap();
+        assert_eq!(transport.pending.len(), 2);
+        assert!(matches!(transport.update(), Err(Error::Validation(_))));
+        assert_eq!(transport.state.len(), 2);
+        assert_eq!(transport.etag.as_deref(), Some("\"1\""));
+    }
+
+    #[test]
+    fn fetch_sends_etag_and_detects_unchanged_snapshot() {
+        let (url, rx) = serve(vec![
+            "HTTP/1.1 200 OK\r\nETag: \"abc\"\r\nContent-Length: 9\r\nConnection: close\r\n\r\n{\"a\":{}}\n".to_string(),
+            "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n".to_string(),
+        ]);
+        let transport = transport(&url);
+
+        let (snapshot, etag) = transport.fetch(None).unwrap().unwrap();
+        assert_eq!(rx.recv().unwrap(), None);
+        assert_eq!(snapshot, state(&[("a", "{}")]));
+        assert_eq!(etag.as_deref(), Some("\"abc\""));
+
+        assert!(transport.fetch(etag.as_deref()).unwrap().is_none());
+        assert_eq!(rx.recv().unwrap().as_deref(), Some("\"abc\""));
+    }
+
+    #[test]
+    fn fetch_fails_on_unexpected_status() {
+        let (url, _rx) = serve(vec![
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                .to_string(),
+            "HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n".to_string(),
+        ]);
+        let transport = transport(&url);
+        assert!(matches!(transport.fetch(None), Err(Error::Http(_))));
+        assert!(matches!(transport.fetch(None), Err(Error::Http(_))));
+    }
+}
@@ -0,0 +1,207 @@
+use crate::error::Error;
+use crate::payload::{Keydata, Message};
+
+use log::{debug, info};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+// Public key file with optional overrides for its keys, either the path or an object
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(from = "KeyFileEntry")]
+pub struct KeyFile {
+    pub file: String,
+    pub hostname: Option<Vec<String>>,
+    pub comment: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum KeyFileEntry {
+    Path(String),
+    Entry {
+        file: String,
+        hostname: Option<Vec<String>>,
+        comment: Option<String>,
+    },
+}
+
+impl From<KeyFileEntry> for KeyFile {
+    fn from(entry: KeyFileEntry) -> Self {
+        match entry {
+            KeyFileEntry::Path(file) => KeyFile::new(&file),
+            KeyFileEntry::Entry {
+                file,
+                hostname,
+                comment,
+            } => KeyFile {
+                file,
+                hostname,
+                comment,
+            },
+        }
+    }
+}
+
+impl KeyFile {
+    pub fn new(file: &str) -> Self {
+        KeyFile {
+            file: file.to_string(),
+            hostname: None,
+            comment: None,
+        }
+    }
+}
+
+// Public host keys as created by ssh-keygen -A, e.g. /etc/ssh/ssh_host_ed25519_key.pub
+pub fn find_pub_keys(dir: &str) -> Result<Vec<String>, Error> {
+    let mut result: Vec<String> = Vec::new();
+
+    if dir.is_empty() {
+        return Err(Error::Validation("empty directory".to_string()));
+    }
+
+    debug!("looking for SSH public keys in {}", dir);
+    for entry in (fs::read_dir(Path::new(dir))?).flatten() {
+        let p = entry.path();
+        debug!("processing {:?}", p);
+        if p.is_file() {
+            let fname = match p.to_str() {
+                Some(v) => v,
+                None => {
+                    continue;
+                }
+            };
+
+            let bname = match p.file_name().and_then(|v| v.to_str()) {
+                Some(v) => v,
+                None => {
+                    continue;
+                }
+            };
+            if bname.starts_with("ssh_host_") && bname.ends_with("_key.pub") {
+                result.push(fname.to_string());
+            }
+        }
+    }
+    Ok(result)
+}
+
+// A comment overrides the comments of all key files, the overrides of a key file take precedence
+pub fn read_key_files(
+    files: &[KeyFile],
+    hostname: &[String],
+    comment: Option<&str>,
+) -> Result<Message, Error> {
+    let mut result = Message::new();
+
+    result.hostname = hostname.to_vec();
+    result.published = Some(chrono::Utc::now().timestamp());
+    for key_file in files.iter() {
+        let f = &key_file.file;
+        info!("reading {}", f);
+        let raw = match fs::read_to_string(f) {
+            Ok(v) => v,
+            Err(e) => return Err(Error::Io(format!("can't read {}: {}", f, e))),
+        };
+
+        let mut parsed_key = match parse_public_key(&raw) {
+            Some(v) => v,
+            None => {
+                return Err(Error::Validation(format!(
+                    "{} doesn't contain valid public key data",
+                    f
+                )));
+            }
+        };
+        if let Some(v) = key_file.comment.as_deref().or(comment) {
+            parsed_key.comment = v.to_string();
+        }
+        parsed_key.hostname = key_file.hostname.clone();
+
+        if let Some(fingerprint) = parsed_key.fingerprint() {
+            info!(
+                fingerprint = fingerprint;
+                "found {} key with fingerprint {} in {}", parsed_key.key_type, fingerprint, f
+            );
+        }
+
+        result.keys.push(parsed_key);
+    }
+    result.set_version();
+
+    Ok(result)
+}
+
+// Content of a public key file: <type> <base64 key> [<comment>]
+pub fn parse_public_key(raw: &str) -> Option<Keydata> {
+    let splitted: Vec<&str> = raw.splitn(3, ' ').collect();
+    if splitted.len() < 2 {
+        return None;
+    }
+    Some(Keydata {
+        key_type: splitted[0].to_string(),
+        key: splitted[1].trim().to_string(),
+        comment: match splitted.get(2) {
+            Some(v) => v.trim().to_string(),
+            None => String::new(),
+        },
+        hostname: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mktemp::Temp;
+
+    #[test]
+    fn parse_public_key_splits_fields() {
+        let key = parse_public_key("ssh-ed25519 AAAA root@node01 with spaces\n").unwrap();
+        assert_eq!(key.key_type, "ssh-ed25519");
+        assert_eq!(key.key, "AAAA");
+        assert_eq!(key.comment, "root@node01 with spaces");
+
+        let key = parse_public_key("ssh-rsa BBBB\n").unwrap();
+        assert_eq!(key.key, "BBBB");
+        assert_eq!(key.comment, "");
+
+        assert!(parse_public_key("garbage").is_none());
+    }
+
+    #[test]
+    fn key_file_is_path_or_object() {
+        let parsed: Vec<KeyFile> =
+            serde_yaml::from_str("- /etc/ssh/a.pub\n- file: /etc/ssh/b.pub\n  hostname: [alias]\n")
+                .unwrap();
+        assert_eq!(parsed[0], KeyFile::new("/etc/ssh/a.pub"));
+        assert_eq!(parsed[1].file, "/etc/ssh/b.pub");
+        assert_eq!(parsed[1].hostname, Some(vec!["alias".to_string()]));
+        assert_eq!(parsed[1].comment, None);
+    }
+
+    #[test]
+    fn read_key_files_applies_overrides() {
+        let first = Temp::new_file().unwrap();
+        let second = Temp::new_file().unwrap();
+        fs::write(&first, "ssh-ed25519 AAAA root@node01\n").unwrap();
+        fs::write(&second, "ssh-rsa BBBB root@node01\n").unwrap();
+
+        let files = vec![
+            KeyFile::new(first.to_str().unwrap()),
+            KeyFile {
+                file: second.to_str().unwrap().to_string(),
+                hostname: Some(vec!["alias".to_string()]),
+                comment: Some("rsa".to_string()),
+            },
+        ];
+        let msg = read_key_files(&files, &["node01".to_string()], Some("cluster")).unwrap();
+
+        assert_eq!(msg.hostname, vec!["node01".to_string()]);
+        assert_eq!(msg.keys[0].comment, "cluster");
+        assert_eq!(msg.keys[0].hostname, None);
+        assert_eq!(msg.keys[1].comment, "rsa");
+        assert_eq!(msg.keys[1].hostname, Some(vec!["alias".to_string()]));
+        assert_eq!(msg.version.as_deref(), Some("2.0"));
+    }
+}
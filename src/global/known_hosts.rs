@@ -0,0 +1,70 @@
+use crate::error::Error;
+use crate::state::KeyMap;
+
+use log::info;
+use mktemp::Temp;
+use std::fs;
+
+pub fn render(data: &KeyMap) -> String {
+    let mut keys: Vec<String> = Vec::new();
+    for (_, value) in data.iter() {
+        for key in value.keys.iter() {
+            keys.push(format!(
+                "{} {} {} {}",
+                value.hostnames(key).join(","),
+                key.key_type,
+                key.key,
+                key.comment
+            ));
+        }
+    }
+
+    let mut content = keys.join("\n");
+    content.push('\n');
+    content
+}
+
+// The file is written to a temporary file in tmpdir first and renamed, so tmpdir should be
+// on the same file system
+pub fn write(file: &str, data: &KeyMap, tmpdir: &str) -> Result<(), Error> {
+    let tempfile = Temp::new_file_in(tmpdir)?;
+    if let Some(tempfile_name) = tempfile.to_str() {
+        info!("writing key data to {}", tempfile_name);
+        fs::write(tempfile_name, render(data))?;
+
+        info!("replacing {} with new content from {}", file, tempfile_name);
+        fs::rename(tempfile_name, file)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payload::{Keydata, Message};
+
+    #[test]
+    fn render_uses_host_names_of_keys() {
+        let mut msg = Message::new();
+        msg.hostname = vec!["node01".to_string(), "10.0.0.1".to_string()];
+        msg.keys.push(Keydata {
+            key_type: "ssh-ed25519".to_string(),
+            key: "AAAA".to_string(),
+            comment: "root@node01".to_string(),
+            hostname: None,
+        });
+        msg.keys.push(Keydata {
+            key_type: "ssh-rsa".to_string(),
+            key: "BBBB".to_string(),
+            comment: "root@node01".to_string(),
+            hostname: Some(vec!["node01.example.com".to_string()]),
+        });
+        let mut map = KeyMap::new();
+        map.insert("node01", msg);
+
+        assert_eq!(
+            render(&map),
+            "node01,10.0.0.1 ssh-ed25519 AAAA root@node01\nnode01.example.com ssh-rsa BBBB root@node01\n"
+        );
+    }
+}
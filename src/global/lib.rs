@@ -1,5 +1,20 @@
 pub mod constants;
+pub mod duration;
+pub mod encryption;
+pub mod error;
+pub mod etcd;
+pub mod filesystem;
+pub mod http;
+pub mod keys;
+pub mod known_hosts;
 pub mod logging;
+pub mod matcher;
 pub mod mqtt;
 pub mod payload;
+pub mod redis;
+pub mod signals;
+pub mod state;
+pub mod subscribe;
+pub mod systemd;
+pub mod transport;
 pub mod usage;
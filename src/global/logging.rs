@@ -1,15 +1,278 @@
-pub fn init(level: log::LevelFilter) -> Result<(), fern::InitError> {
-    fern::Dispatch::new()
-        .format(|out, msg, record| {
-            out.finish(format_args!(
-                "{:<6}: {} {}",
-                record.level(),
-                chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%z"),
-                msg
-            ))
-        })
-        .level(level)
-        .chain(std::io::stdout())
-        .apply()?;
+use crate::constants;
+use crate::error::Error;
+
+use log::kv;
+use serde::Deserialize;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::net::UnixDatagram;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Logging {
+    #[serde(default)]
+    pub format: Format,
+    pub file: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+    Syslog,
+    Journald,
+}
+
+// Destination of text and json logs if no log file is configured. Tools printing reports to
+// standard output log to standard error, so the logs don't mix with the report
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Output {
+    Stdout,
+    Stderr,
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "syslog" => Ok(Format::Syslog),
+            "journald" => Ok(Format::Journald),
+            _ => Err(Error::Config(format!("invalid log format {}", s))),
+        }
+    }
+}
+
+pub fn init(level: log::LevelFilter, cfg: &Logging, default: Output) -> Result<(), Error> {
+    let dispatch = fern::Dispatch::new().level(level);
+
+    let dispatch = match cfg.format {
+        Format::Text => dispatch.format(format_text).chain(output(cfg, default)?),
+        Format::Json => dispatch.format(format_json).chain(output(cfg, default)?),
+        Format::Syslog => {
+            let formatter = syslog::Formatter3164 {
+                facility: syslog::Facility::LOG_DAEMON,
+                hostname: None,
+                process: process_name(),
+                pid: std::process::id(),
+            };
+            let logger = match syslog::unix(formatter) {
+                Ok(v) => v,
+                Err(e) => {
+                    return Err(Error::Io(format!("can't connect to syslog: {}", e)));
+                }
+            };
+            dispatch.chain(logger)
+        }
+        Format::Journald => {
+            let journal: Box<dyn log::Log> = Box::new(Journald::new()?);
+            dispatch.chain(journal)
+        }
+    };
+
+    if let Err(e) = dispatch.apply() {
+        return Err(Error::Config(format!("can't initialise logging: {}", e)));
+    }
     Ok(())
 }
+
+// Initialise logging, overriding the configured log format by the format from the command line.
+// There is nowhere to log a failure, so it's printed to standard error and the process exits
+pub fn init_or_exit(
+    level: log::LevelFilter,
+    cfg: &Logging,
+    format: Option<Format>,
+    default: Output,
+) {
+    let mut cfg = cfg.clone();
+    if let Some(v) = format {
+        cfg.format = v;
+    }
+    if let Err(e) = init(level, &cfg, default) {
+        eprintln!("Error: Can't initialise logging: {}", e);
+        std::process::exit(e.exit_code());
+    }
+}
+
+fn output(cfg: &Logging, default: Output) -> Result<fern::Output, Error> {
+    match &cfg.file {
+        Some(v) => {
+            let file: Box<dyn Write + Send> = Box::new(LogFile::new(v)?);
+            Ok(file.into())
+        }
+        None => match default {
+            Output::Stdout => Ok(io::stdout().into()),
+            Output::Stderr => Ok(io::stderr().into()),
+        },
+    }
+}
+
+fn format_text(out: fern::FormatCallback, msg: &fmt::Arguments, record: &log::Record) {
+    out.finish(format_args!(
+        "{:<6}: {} {}",
+        record.level(),
+        chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%z"),
+        msg
+    ))
+}
+
+fn format_json(out: fern::FormatCallback, msg: &fmt::Arguments, record: &log::Record) {
+    let mut line = serde_json::Map::new();
+    line.insert(
+        "timestamp".to_string(),
+        chrono::Local::now()
+            .format("%Y-%m-%dT%H:%M:%S%z")
+            .to_string()
+            .into(),
+    );
+    line.insert("level".to_string(), record.level().to_string().into());
+    line.insert("message".to_string(), msg.to_string().into());
+    for (key, value) in key_values(record) {
+        line.insert(key, value.into());
+    }
+    out.finish(format_args!("{}", serde_json::Value::Object(line)))
+}
+
+fn key_values(record: &log::Record) -> Vec<(String, String)> {
+    struct Collect(Vec<(String, String)>);
+
+    impl<'kvs> kv::VisitSource<'kvs> for Collect {
+        fn visit_pair(
+            &mut self,
+            key: kv::Key<'kvs>,
+            value: kv::Value<'kvs>,
+        ) -> Result<(), kv::Error> {
+            self.0.push((key.to_string(), value.to_string()));
+            Ok(())
+        }
+    }
+
+    let mut result = Collect(Vec::new());
+    // XXX: Collecting into a vector can't fail
+    let _ = record.key_values().visit(&mut result);
+    result.0
+}
+
+fn process_name() -> String {
+    match std::env::args().next() {
+        Some(v) => match v.rsplit('/').next() {
+            Some(b) => b.to_string(),
+            None => v,
+        },
+        None => constants::PACKAGE_NAME.to_string(),
+    }
+}
+
+struct LogFile {
+    path: String,
+    file: File,
+    reopen: Arc<AtomicBool>,
+}
+
+impl LogFile {
+    fn new(path: &str) -> Result<Self, Error> {
+        let reopen = Arc::new(AtomicBool::new(false));
+        signal_hook::flag::register(signal_hook::consts::SIGHUP, Arc::clone(&reopen))?;
+        Ok(LogFile {
+            path: path.to_string(),
+            file: open_log_file(path)?,
+            reopen,
+        })
+    }
+}
+
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    // Reopen after a complete line has been written, so log rotation never splits a line
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.reopen.swap(false, Ordering::Relaxed) {
+            self.file = open_log_file(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
+fn open_log_file(path: &str) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+struct Journald {
+    socket: UnixDatagram,
+    identifier: String,
+}
+
+impl Journald {
+    fn new() -> Result<Self, Error> {
+        let socket = UnixDatagram::unbound()?;
+        if let Err(e) = socket.connect(constants::JOURNALD_SOCKET) {
+            return Err(Error::Io(format!(
+                "can't connect to journald socket {}: {}",
+                constants::JOURNALD_SOCKET,
+                e
+            )));
+        }
+        Ok(Journald {
+            socket,
+            identifier: process_name(),
+        })
+    }
+}
+
+impl log::Log for Journald {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        let priority = match record.level() {
+            log::Level::Error => "3",
+            log::Level::Warn => "4",
+            log::Level::Info => "6",
+            log::Level::Debug | log::Level::Trace => "7",
+        };
+
+        let mut data: Vec<u8> = Vec::new();
+        append_journal_field(&mut data, "PRIORITY", priority);
+        append_journal_field(&mut data, "SYSLOG_IDENTIFIER", &self.identifier);
+        append_journal_field(&mut data, "MESSAGE", &record.args().to_string());
+        for (key, value) in key_values(record) {
+            append_journal_field(&mut data, &journal_field_name(&key), &value);
+        }
+
+        // XXX: There is nowhere left to report a failure of the logging backend
+        let _ = self.socket.send(&data);
+    }
+
+    fn flush(&self) {}
+}
+
+fn journal_field_name(key: &str) -> String {
+    key.to_uppercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+fn append_journal_field(data: &mut Vec<u8>, key: &str, value: &str) {
+    data.extend_from_slice(key.as_bytes());
+    if value.contains('\n') {
+        // Values containing newlines must be sent length-prefixed
+        data.push(b'\n');
+        data.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        data.push(b'=');
+    }
+    data.extend_from_slice(value.as_bytes());
+    data.push(b'\n');
+}
@@ -0,0 +1,36 @@
+use crate::error::Error;
+
+// Host name patterns, either shell style globs or regular expressions
+pub enum Matcher {
+    Glob(glob::Pattern),
+    Regex(regex::Regex),
+}
+
+impl Matcher {
+    pub fn new(pattern: &str, regex: bool) -> Result<Self, Error> {
+        if regex {
+            match regex::Regex::new(pattern) {
+                Ok(v) => Ok(Matcher::Regex(v)),
+                Err(e) => Err(Error::Validation(format!(
+                    "invalid regular expression {}: {}",
+                    pattern, e
+                ))),
+            }
+        } else {
+            match glob::Pattern::new(pattern) {
+                Ok(v) => Ok(Matcher::Glob(v)),
+                Err(e) => Err(Error::Validation(format!(
+                    "invalid pattern {}: {}",
+                    pattern, e
+                ))),
+            }
+        }
+    }
+
+    pub fn matches(&self, host: &str) -> bool {
+        match self {
+            Matcher::Glob(v) => v.matches(host),
+            Matcher::Regex(v) => v.is_match(host),
+        }
+    }
+}
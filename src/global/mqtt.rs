@@ -1,14 +1,17 @@
 use crate::constants;
+use crate::error::Error;
+use crate::transport::{Transport, Update};
 
-use log::{error, warn};
+use log::{debug, error, info, warn};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use serde::Deserialize;
-use std::error::Error;
-use std::time::Duration;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
 use std::{thread, time};
+use url::Url;
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq)]
 pub struct MQTT {
     pub broker: String,
     #[serde(default)]
@@ -30,6 +33,49 @@ pub struct MQTT {
     pub user: String,
 }
 
+// Tools publishing key data use the topic as prefix, so it can't contain wildcards. Subscribing
+// tools receive the key data of all hosts, so their topic must contain wildcards
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Topic {
+    Publish,
+    Subscribe,
+}
+
+pub fn validate(cfg: &MQTT, topic: Topic) -> Result<(), Error> {
+    if cfg.qos > 2 || cfg.qos < 0 {
+        return Err(Error::Config("invalid MQTT QoS setting".to_string()));
+    }
+
+    let wildcards = cfg.topic.contains('+') || cfg.topic.contains('#');
+    match topic {
+        Topic::Publish if cfg.topic.is_empty() || wildcards => {
+            return Err(Error::Config(
+                "invalid MQTT topic, wildcards are not allowed in publishing topic".to_string(),
+            ));
+        }
+        Topic::Subscribe if cfg.topic.is_empty() || !wildcards => {
+            return Err(Error::Config(
+                "invalid MQTT topic, wildcards must be present in subscribed topic".to_string(),
+            ));
+        }
+        _ => {}
+    };
+
+    if cfg.timeout == 0 {
+        return Err(Error::Config("invalid MQTT timeout".to_string()));
+    }
+
+    if cfg.reconnect_timeout == 0 {
+        return Err(Error::Config("invalid MQTT reconnect timeout".to_string()));
+    }
+
+    if let Err(e) = Url::parse(&cfg.broker) {
+        return Err(Error::Config(format!("invalid MQTT broker url: {}", e)));
+    }
+
+    Ok(())
+}
+
 fn mqtt_default_timeout() -> u64 {
     constants::DEFAULT_MQTT_TIMEOUT
 }
@@ -46,9 +92,7 @@ fn mqtt_default_client_id() -> String {
         .collect()
 }
 
-pub fn connection_builder(
-    cfg: &MQTT,
-) -> Result<paho_mqtt::connect_options::ConnectOptions, Box<dyn Error>> {
+pub fn connection_builder(cfg: &MQTT) -> Result<paho_mqtt::connect_options::ConnectOptions, Error> {
     let mut sslopts = paho_mqtt::ssl_options::SslOptionsBuilder::new();
     if cfg.broker.starts_with("ssl://") || cfg.broker.starts_with("tls://") {
         if !cfg.ca_cert.is_empty() {
@@ -76,7 +120,7 @@ pub fn connection_builder(
     Ok(client_opt)
 }
 
-pub fn client_builder(cfg: &MQTT) -> Result<paho_mqtt::client::Client, Box<dyn Error>> {
+pub fn client_builder(cfg: &MQTT) -> Result<paho_mqtt::client::Client, Error> {
     let client_opts = paho_mqtt::CreateOptionsBuilder::new()
         .client_id(&cfg.client_id)
         .server_uri(&cfg.broker)
@@ -91,7 +135,7 @@ pub fn connect(
     cfg: &MQTT,
     client: &paho_mqtt::client::Client,
     option: &paho_mqtt::connect_options::ConnectOptions,
-) -> Result<paho_mqtt::server_response::ServerResponse, Box<dyn Error>> {
+) -> Result<paho_mqtt::server_response::ServerResponse, Error> {
     let mut ticktock: u64 = 0;
     let cstatus: paho_mqtt::server_response::ServerResponse;
     let one_second = time::Duration::from_secs(1);
@@ -100,19 +144,24 @@ pub fn connect(
         let mco = option.clone();
         cstatus = match client.connect(mco) {
             Err(e) => {
-                error!("connection to MQTT broker {} failed: {}", cfg.broker, e);
+                error!(
+                    broker = cfg.broker;
+                    "connection to MQTT broker {} failed: {}", cfg.broker, e
+                );
                 if cfg.reconnect_timeout != 0 && ticktock > cfg.reconnect_timeout {
-                    return Err(Box::new(e));
+                    return Err(Error::from(e));
                 }
                 thread::sleep(one_second);
                 ticktock += 1;
                 if cfg.reconnect_timeout != 0 {
                     warn!(
+                        broker = cfg.broker;
                         "retrying to connect to MQTT broker {} - attempt {}/{}",
                         cfg.broker, ticktock, cfg.reconnect_timeout
                     );
                 } else {
                     warn!(
+                        broker = cfg.broker;
                         "retrying to connect to MQTT broker {} - attempt {}",
                         cfg.broker, ticktock
                     );
@@ -126,46 +175,337 @@ pub fn connect(
     Ok(cstatus)
 }
 
-pub fn reconnect(
-    cfg: &MQTT,
-    client: &paho_mqtt::client::Client,
-) -> Result<paho_mqtt::server_response::ServerResponse, Box<dyn Error>> {
-    let mut ticktock: u64 = 0;
-    let cstatus: paho_mqtt::server_response::ServerResponse;
-    let one_second = time::Duration::from_secs(1);
+pub fn disconnect(client: &paho_mqtt::client::Client) -> Result<(), Error> {
+    if let Err(e) = client.disconnect(None) {
+        return Err(Error::from(e));
+    };
+    Ok(())
+}
 
-    loop {
-        cstatus = match client.reconnect() {
-            Err(e) => {
-                error!("reconnect to MQTT broker {} failed: {}", cfg.broker, e);
-                if cfg.reconnect_timeout != 0 && ticktock > cfg.reconnect_timeout {
-                    return Err(Box::new(e));
+// Retained messages are sent by the broker directly after subscribing, so stop
+// collecting if no new message arrived within the given window
+pub fn fetch_retained(cfg: &MQTT, window: Duration) -> Result<BTreeMap<String, Vec<u8>>, Error> {
+    let mut result: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+    let conn = connection_builder(cfg)?;
+    let client = client_builder(cfg)?;
+    let messages = client.start_consuming();
+
+    info!(broker = cfg.broker; "connecting to MQTT broker {}", cfg.broker);
+    connect(cfg, &client, &conn)?;
+
+    info!(
+        broker = cfg.broker, topic = cfg.topic;
+        "subscribing to topic {} on {} with QoS {}", cfg.topic, cfg.broker, cfg.qos
+    );
+    client.subscribe(&cfg.topic, cfg.qos)?;
+
+    let mut last_message = Instant::now();
+    while last_message.elapsed() < window {
+        match messages.recv_timeout(window.saturating_sub(last_message.elapsed())) {
+            Ok(Some(msg)) => {
+                debug!(topic = msg.topic(); "received data on {}", msg.topic());
+                if msg.payload().is_empty() {
+                    result.remove(msg.topic());
+                } else {
+                    result.insert(msg.topic().to_string(), msg.payload().to_vec());
                 }
-                thread::sleep(one_second);
-                ticktock += 1;
-                if cfg.reconnect_timeout != 0 {
-                    warn!(
-                        "retrying to reconnect to MQTT broker {} - attempt {}/{}",
-                        cfg.broker, ticktock, cfg.reconnect_timeout
+                last_message = Instant::now();
+            }
+            Ok(None) => {
+                if !client.is_connected() {
+                    return Err(Error::Mqtt(format!(
+                        "connection to MQTT broker {} was lost",
+                        cfg.broker
+                    )));
+                }
+            }
+            Err(e) if e.is_timeout() => break,
+            Err(e) => return Err(Error::Mqtt(e.to_string())),
+        }
+    }
+
+    client.stop_consuming();
+    info!(broker = cfg.broker; "disconnecting from MQTT broker {}", cfg.broker);
+    if let Err(e) = disconnect(&client) {
+        warn!("diconnect from MQTT broker failed: {}", e);
+    };
+
+    Ok(result)
+}
+
+// Publish retained messages, the topics are relative to the configured topic
+pub fn publish_retained(cfg: &MQTT, messages: &BTreeMap<String, Vec<u8>>) -> Result<(), Error> {
+    let conn = connection_builder(cfg)?;
+    let client = client_builder(cfg)?;
+
+    info!(broker = cfg.broker; "connecting to MQTT broker {}", cfg.broker);
+    connect(cfg, &client, &conn)?;
+
+    for (subtopic, payload) in messages.iter() {
+        let topic = format!("{}/{}", cfg.topic, subtopic);
+        let msg = paho_mqtt::message::MessageBuilder::new()
+            .topic(&topic)
+            .payload(payload.as_slice())
+            .qos(cfg.qos)
+            .retained(true)
+            .finalize();
+
+        info!(
+            broker = cfg.broker, topic = topic;
+            "sending data to topic {} on MQTT broker {}", topic, cfg.broker
+        );
+        if let Err(e) = client.publish(msg) {
+            return Err(Error::Mqtt(format!(
+                "sending message to MQTT broker failed - {}",
+                e
+            )));
+        }
+    }
+
+    info!(broker = cfg.broker; "disconnecting from MQTT broker {}", cfg.broker);
+    if let Err(e) = disconnect(&client) {
+        warn!("diconnect from MQTT broker failed: {}", e);
+    };
+
+    Ok(())
+}
+
+pub struct MqttTransport {
+    cfg: MQTT,
+    client: Option<paho_mqtt::client::Client>,
+    messages: Option<paho_mqtt::Receiver<Option<paho_mqtt::message::Message>>>,
+    connected: bool,
+    reconnect_since: Option<Instant>,
+    reconnect_attempts: u64,
+    next_reconnect: Option<Instant>,
+}
+
+impl MqttTransport {
+    pub fn new(cfg: &MQTT) -> Self {
+        MqttTransport {
+            cfg: cfg.clone(),
+            client: None,
+            messages: None,
+            connected: false,
+            reconnect_since: None,
+            reconnect_attempts: 0,
+            next_reconnect: None,
+        }
+    }
+
+    // Key data is stored in <topic>/<host>, a trailing wildcard of the configured topic is ignored
+    fn base_topic(&self) -> String {
+        self.cfg
+            .topic
+            .trim_end_matches("/+")
+            .trim_end_matches("/#")
+            .to_string()
+    }
+
+    fn subscription_topic(&self) -> String {
+        if self.cfg.topic.contains('+') || self.cfg.topic.contains('#') {
+            self.cfg.topic.clone()
+        } else {
+            format!("{}/+", self.cfg.topic)
+        }
+    }
+}
+
+impl Transport for MqttTransport {
+    fn location(&self) -> String {
+        format!("MQTT broker {}", self.cfg.broker)
+    }
+
+    fn publish(&mut self, data: &BTreeMap<String, Vec<u8>>) -> Result<(), Error> {
+        let mut cfg = self.cfg.clone();
+        cfg.topic = self.base_topic();
+        publish_retained(&cfg, data)
+    }
+
+    fn delete(&mut self, hosts: &[String]) -> Result<(), Error> {
+        let data: BTreeMap<String, Vec<u8>> =
+            hosts.iter().map(|h| (h.clone(), Vec::new())).collect();
+        self.publish(&data)
+    }
+
+    fn list(&mut self, wait: Duration) -> Result<BTreeMap<String, Vec<u8>>, Error> {
+        let mut result: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+        let mut cfg = self.cfg.clone();
+        cfg.topic = self.subscription_topic();
+
+        for (topic, payload) in fetch_retained(&cfg, wait)?.into_iter() {
+            if let Some(host) = topic.rsplit('/').next() {
+                result.insert(host.to_string(), payload);
+            }
+        }
+        Ok(result)
+    }
+
+    fn subscribe(&mut self) -> Result<(), Error> {
+        let client = client_builder(&self.cfg)?;
+        let messages = client.start_consuming();
+        let conn = connection_builder(&self.cfg)?;
+        let topic = self.subscription_topic();
+
+        info!(broker = self.cfg.broker; "connecting to MQTT broker {}", self.cfg.broker);
+        let cstatus = connect(&self.cfg, &client, &conn)?;
+        info!(
+            broker = self.cfg.broker;
+            "connected to MQTT broker {} with client ID {}",
+            self.cfg.broker, self.cfg.client_id
+        );
+
+        match cstatus.connect_response() {
+            Some(v) => {
+                if !v.session_present {
+                    info!(
+                        broker = self.cfg.broker, topic = topic;
+                        "subscribing to topic {} on {} with QoS {}",
+                        topic, self.cfg.broker, self.cfg.qos
                     );
-                } else {
+                    if let Err(e) = client.subscribe(&topic, self.cfg.qos) {
+                        return Err(Error::Mqtt(format!(
+                            "can't subscribe to topic {} - {}",
+                            topic, e
+                        )));
+                    }
+                }
+            }
+            None => {
+                return Err(Error::Mqtt(
+                    "empty connect_response result from MQTT connection".to_string(),
+                ));
+            }
+        };
+
+        self.client = Some(client);
+        self.messages = Some(messages);
+        self.connected = false;
+        self.reconnect_since = None;
+        self.reconnect_attempts = 0;
+        self.next_reconnect = None;
+        Ok(())
+    }
+
+    fn poll(&mut self, timeout: Duration) -> Result<Option<Update>, Error> {
+        let (client, messages) = match (&self.client, &self.messages) {
+            (Some(c), Some(m)) => (c, m),
+            _ => return Err(Error::Mqtt("not subscribed to MQTT topic".to_string())),
+        };
+
+        // The initial connect is reported by the first poll. Reconnects are attempted once per
+        // second, returning between attempts, so callers keep running (e.g. the systemd watchdog)
+        if !self.connected {
+            if let Some(v) = self.next_reconnect {
+                let wait = v.saturating_duration_since(Instant::now());
+                if wait > timeout {
+                    thread::sleep(timeout);
+                    return Ok(None);
+                }
+                thread::sleep(wait);
+            }
+
+            if !client.is_connected() {
+                if let Err(e) = client.reconnect() {
+                    error!(
+                        broker = self.cfg.broker;
+                        "reconnect to MQTT broker {} failed: {}", self.cfg.broker, e
+                    );
+                    let since = *self.reconnect_since.get_or_insert_with(Instant::now);
+                    if self.cfg.reconnect_timeout != 0
+                        && since.elapsed() > Duration::from_secs(self.cfg.reconnect_timeout)
+                    {
+                        return Err(Error::from(e));
+                    }
+                    self.reconnect_attempts += 1;
+                    self.next_reconnect = Some(Instant::now() + Duration::from_secs(1));
                     warn!(
+                        broker = self.cfg.broker;
                         "retrying to reconnect to MQTT broker {} - attempt {}",
-                        cfg.broker, ticktock
+                        self.cfg.broker, self.reconnect_attempts
                     );
+                    return Ok(None);
                 }
-                continue;
             }
-            Ok(v) => v,
-        };
-        break;
+            self.connected = true;
+            self.reconnect_since = None;
+            self.reconnect_attempts = 0;
+            self.next_reconnect = None;
+            return Ok(Some(Update::Connected));
+        }
+
+        match messages.recv_timeout(timeout) {
+            Ok(Some(msg)) => {
+                info!(
+                    topic = msg.topic();
+                    "received data on {} with qos {}", msg.topic(), msg.qos()
+                );
+                match msg.topic().rsplit('/').next() {
+                    Some(host) => Ok(Some(Update::Data(host.to_string(), msg.payload().to_vec()))),
+                    None => Ok(None),
+                }
+            }
+            Ok(None) if !client.is_connected() => {
+                warn!(broker = self.cfg.broker; "connection to broker was lost, reconnecting");
+                self.connected = false;
+                Ok(Some(Update::Disconnected))
+            }
+            Ok(None) => Ok(None),
+            Err(e) if e.is_timeout() => Ok(None),
+            Err(e) => Err(Error::Mqtt(format!(
+                "can't receive messages from MQTT broker: {}",
+                e
+            ))),
+        }
+    }
+
+    fn close(&mut self) {
+        if let Some(client) = self.client.take() {
+            client.stop_consuming();
+            info!(broker = self.cfg.broker; "disconnecting from MQTT broker {}", self.cfg.broker);
+            if let Err(e) = disconnect(&client) {
+                warn!("diconnect from MQTT broker failed: {}", e);
+            };
+        }
+        self.messages = None;
+        self.connected = false;
     }
-    Ok(cstatus)
 }
 
-pub fn disconnect(client: &paho_mqtt::client::Client) -> Result<(), Box<dyn Error>> {
-    if let Err(e) = client.disconnect(None) {
-        return Err(Box::new(e));
-    };
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mqtt(topic: &str) -> MQTT {
+        serde_yaml::from_str(&format!(
+            "broker: tcp://localhost:1883\nuser: u\npassword: p\ntopic: '{}'\n",
+            topic
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn validate_checks_wildcards_of_topic() {
+        assert!(validate(&mqtt("ssh/pub/keys"), Topic::Publish).is_ok());
+        assert!(validate(&mqtt("ssh/pub/keys/+"), Topic::Publish).is_err());
+        assert!(validate(&mqtt("ssh/pub/keys/+"), Topic::Subscribe).is_ok());
+        assert!(validate(&mqtt("ssh/pub/#"), Topic::Subscribe).is_ok());
+        assert!(validate(&mqtt("ssh/pub/keys"), Topic::Subscribe).is_err());
+        assert!(validate(&mqtt(""), Topic::Publish).is_err());
+    }
+
+    #[test]
+    fn validate_rejects_invalid_settings() {
+        let mut cfg = mqtt("ssh/pub/keys");
+        cfg.qos = 3;
+        assert!(validate(&cfg, Topic::Publish).is_err());
+
+        let mut cfg = mqtt("ssh/pub/keys");
+        cfg.broker = "not a url".to_string();
+        assert!(validate(&cfg, Topic::Publish).is_err());
+
+        let mut cfg = mqtt("ssh/pub/keys");
+        cfg.timeout = 0;
+        assert!(validate(&cfg, Topic::Publish).is_err());
+    }
 }
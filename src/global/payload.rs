@@ -1,9 +1,28 @@
+use crate::constants;
+use crate::error::Error;
+
+use base64::Engine;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::io::{Read, Write};
+
+// Magic numbers of compressed payloads, JSON payloads always start with '{'
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
 
+// Schema version of the payload as "<major>.<minor>". Minor versions only add optional fields,
+// which older readers ignore. Payloads without version were written by collectors before
+// versioning was introduced and are treated as version 1.0. Version 2.0 added host names of keys
 #[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
 pub struct Message {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
     pub hostname: Vec<String>,
     pub keys: Vec<Keydata>,
+    // UNIX timestamp of the publication, missing in data from older collectors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published: Option<i64>,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
@@ -12,15 +31,203 @@ pub struct Keydata {
     pub key_type: String,
     pub key: String,
     pub comment: String,
+    // Host names of this key only, replacing the host names of the message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<Vec<String>>,
 }
 
 impl Message {
     pub fn new() -> Self {
-        Message {
+        let mut result = Message {
+            version: None,
             hostname: Vec::new(),
             keys: Vec::new(),
+            published: None,
+        };
+        result.set_version();
+        result
+    }
+
+    // Readers of version 1 would ignore the host names of keys and use the host names of the
+    // message for all keys, so only payloads using them are written as version 2
+    pub fn set_version(&mut self) {
+        let version = if self.keys.iter().any(|k| k.hostname.is_some()) {
+            format!(
+                "{}.{}",
+                constants::PAYLOAD_VERSION_MAJOR,
+                constants::PAYLOAD_VERSION_MINOR
+            )
+        } else {
+            format!("1.{}", constants::PAYLOAD_VERSION_1_MINOR)
+        };
+        self.version = Some(version);
+    }
+
+    // The version is checked before the payload is parsed, a newer major version may have changed
+    // the layout of the known fields
+    pub fn parse(raw: &[u8]) -> Result<Self, Error> {
+        Self::parse_limited(raw, constants::DEFAULT_MAX_PAYLOAD_SIZE)
+    }
+
+    // Compressed payloads are decompressed first, limit is the maximal size of the decompressed
+    // payload
+    pub fn parse_limited(raw: &[u8], limit: usize) -> Result<Self, Error> {
+        let raw_str = String::from_utf8(decode(raw, limit)?.into_owned())?;
+        let header: Header = serde_json::from_str(&raw_str)?;
+        check_version(header.version.as_deref())?;
+        let parsed = serde_json::from_str(&raw_str)?;
+        Ok(parsed)
+    }
+
+    // Host names to be used for key in ssh_known_hosts
+    pub fn hostnames<'a>(&'a self, key: &'a Keydata) -> &'a [String] {
+        match &key.hostname {
+            Some(v) => v,
+            None => &self.hostname,
         }
     }
+
+    // Host names of the message and all keys, e.g. for selection of hosts
+    pub fn all_hostnames(&self) -> impl Iterator<Item = &String> {
+        self.hostname.iter().chain(
+            self.keys
+                .iter()
+                .filter_map(|k| k.hostname.as_ref())
+                .flatten(),
+        )
+    }
+
+    // The schema version doesn't count as a change of the key data
+    pub fn same_key_data(&self, other: &Message) -> bool {
+        self.hostname == other.hostname && self.keys == other.keys
+    }
+
+    // Whitespace in hostnames or key data would corrupt the ssh_known_hosts file
+    pub fn validate(&self) -> Result<(), Error> {
+        validate_hostnames(&self.hostname)?;
+        for key in self.keys.iter() {
+            if let Some(v) = &key.hostname {
+                validate_hostnames(v)?;
+            }
+            if key.key_type.is_empty() || key.key_type.contains(char::is_whitespace) {
+                return Err(Error::Validation(format!(
+                    "invalid key type {:?}",
+                    key.key_type
+                )));
+            }
+            if key.key.is_empty() || key.key.contains(char::is_whitespace) {
+                return Err(Error::Validation("invalid key data".to_string()));
+            }
+            if key.comment.contains(['\n', '\r']) {
+                return Err(Error::Validation("invalid key comment".to_string()));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn validate_hostnames(hostnames: &[String]) -> Result<(), Error> {
+    if hostnames.is_empty() {
+        return Err(Error::Validation("empty hostname list".to_string()));
+    }
+    for h in hostnames.iter() {
+        if h.is_empty() || h.contains(char::is_whitespace) || h.contains(',') {
+            return Err(Error::Validation(format!("invalid hostname {:?}", h)));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    #[serde(default)]
+    version: Option<String>,
+}
+
+pub fn check_version(version: Option<&str>) -> Result<(), Error> {
+    let version = match version {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+
+    let major = match version.split_once('.') {
+        Some((major, minor)) if minor.parse::<u64>().is_ok() => major.parse::<u64>(),
+        _ => version.parse::<u64>(),
+    };
+    match major {
+        Ok(v) if v > constants::PAYLOAD_VERSION_MAJOR => Err(Error::Validation(format!(
+            "unsupported payload version {}, only major version {} or older is supported",
+            version,
+            constants::PAYLOAD_VERSION_MAJOR
+        ))),
+        Ok(_) => Ok(()),
+        Err(_) => Err(Error::Payload(format!(
+            "invalid payload version {:?}",
+            version
+        ))),
+    }
+}
+
+pub fn encode(raw: Vec<u8>, compression: Compression) -> Result<Vec<u8>, Error> {
+    match compression {
+        Compression::None => Ok(raw),
+        Compression::Gzip => {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(&raw)?;
+            Ok(encoder.finish()?)
+        }
+        Compression::Zstd => Ok(zstd::encode_all(raw.as_slice(), 0)?),
+    }
+}
+
+// Decompression stops after limit bytes, so a small compressed payload can't exhaust the memory
+pub fn decode(raw: &[u8], limit: usize) -> Result<Cow<'_, [u8]>, Error> {
+    if raw.len() > limit {
+        return Err(Error::Validation(format!(
+            "payload size of {} bytes exceeds the maximal size of {} bytes",
+            raw.len(),
+            limit
+        )));
+    }
+
+    let decoder: Box<dyn Read> = if raw.starts_with(GZIP_MAGIC) {
+        Box::new(flate2::read::GzDecoder::new(raw))
+    } else if raw.starts_with(ZSTD_MAGIC) {
+        match zstd::stream::read::Decoder::new(raw) {
+            Ok(v) => Box::new(v),
+            Err(e) => {
+                return Err(Error::Payload(format!(
+                    "can't decompress zstd payload: {}",
+                    e
+                )));
+            }
+        }
+    } else {
+        return Ok(Cow::Borrowed(raw));
+    };
+
+    let mut result = Vec::new();
+    if let Err(e) = decoder.take(limit as u64 + 1).read_to_end(&mut result) {
+        return Err(Error::Payload(format!("can't decompress payload: {}", e)));
+    }
+    if result.len() > limit {
+        return Err(Error::Validation(format!(
+            "decompressed payload exceeds the maximal size of {} bytes",
+            limit
+        )));
+    }
+
+    Ok(Cow::Owned(result))
 }
 
 impl Keydata {
@@ -29,6 +236,118 @@ impl Keydata {
             key_type: String::new(),
             key: String::new(),
             comment: String::new(),
+            hostname: None,
+        }
+    }
+
+    // Same format as ssh-keygen -l: SHA256 of the decoded key blob, base64 without padding
+    pub fn fingerprint(&self) -> Option<String> {
+        let blob = match base64::engine::general_purpose::STANDARD.decode(&self.key) {
+            Ok(v) => v,
+            Err(_) => return None,
+        };
+        let digest = Sha256::digest(blob);
+        Some(format!(
+            "SHA256:{}",
+            base64::engine::general_purpose::STANDARD_NO_PAD.encode(digest)
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message() -> Message {
+        let mut msg = Message::new();
+        msg.hostname = vec!["node01".to_string()];
+        msg.keys.push(Keydata {
+            key_type: "ssh-ed25519".to_string(),
+            key: "AAAA".to_string(),
+            comment: "root@node01".to_string(),
+            hostname: None,
+        });
+        msg
+    }
+
+    #[test]
+    fn validate_rejects_invalid_key_data() {
+        assert!(message().validate().is_ok());
+
+        let mut msg = message();
+        msg.hostname = Vec::new();
+        assert!(msg.validate().is_err());
+
+        let mut msg = message();
+        msg.hostname = vec!["node 01".to_string()];
+        assert!(msg.validate().is_err());
+
+        let mut msg = message();
+        msg.keys[0].hostname = Some(vec!["node01,alias".to_string()]);
+        assert!(msg.validate().is_err());
+
+        let mut msg = message();
+        msg.keys[0].key_type = String::new();
+        assert!(msg.validate().is_err());
+
+        let mut msg = message();
+        msg.keys[0].key = "AA AA".to_string();
+        assert!(msg.validate().is_err());
+
+        let mut msg = message();
+        msg.keys[0].comment = "root@node01\nnode02 ssh-rsa BBBB".to_string();
+        assert!(msg.validate().is_err());
+    }
+
+    #[test]
+    fn version_depends_on_host_names_of_keys() {
+        let mut msg = message();
+        msg.set_version();
+        assert_eq!(msg.version.as_deref(), Some("1.1"));
+
+        msg.keys[0].hostname = Some(vec!["alias".to_string()]);
+        msg.set_version();
+        assert_eq!(msg.version.as_deref(), Some("2.0"));
+    }
+
+    #[test]
+    fn check_version_rejects_newer_major_version() {
+        assert!(check_version(None).is_ok());
+        assert!(check_version(Some("1.1")).is_ok());
+        assert!(check_version(Some("2.0")).is_ok());
+        assert!(check_version(Some("2")).is_ok());
+        assert!(matches!(
+            check_version(Some("3.0")),
+            Err(Error::Validation(_))
+        ));
+        assert!(matches!(check_version(Some("1.x")), Err(Error::Payload(_))));
+
+        // The layout of a newer major version isn't parsed at all
+        let raw = br#"{"version":"3.0","hostname":"node01"}"#;
+        assert!(matches!(Message::parse(raw), Err(Error::Validation(_))));
+    }
+
+    #[test]
+    fn decode_stops_at_limit() {
+        let raw = vec![b'{'; 10000];
+        assert!(matches!(decode(&raw, 1000), Err(Error::Validation(_))));
+        assert!(matches!(decode(&raw, 10000), Ok(Cow::Borrowed(_))));
+
+        for compression in [Compression::Gzip, Compression::Zstd] {
+            let compressed = encode(raw.clone(), compression).unwrap();
+            assert!(compressed.len() < 1000);
+            assert!(matches!(
+                decode(&compressed, 1000),
+                Err(Error::Validation(_))
+            ));
+            assert_eq!(decode(&compressed, 10000).unwrap(), raw.as_slice());
         }
     }
+
+    #[test]
+    fn parse_reads_compressed_payload() {
+        let raw = serde_json::to_vec(&message()).unwrap();
+        let compressed = encode(raw, Compression::Zstd).unwrap();
+        assert_eq!(Message::parse(&compressed).unwrap(), message());
+    }
 }
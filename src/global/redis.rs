@@ -0,0 +1,426 @@
+use crate::constants;
+use crate::error::Error;
+use crate::transport::{Transport, Update};
+
+use log::{debug, info, warn};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+use url::Url;
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Redis {
+    #[serde(default)]
+    pub channel: String,
+    #[serde(default)]
+    pub insecure_ssl: bool,
+    pub key: String,
+    #[serde(default)]
+    pub password: String,
+    #[serde(default = "redis_default_timeout")]
+    pub timeout: u64,
+    pub url: String,
+    #[serde(default)]
+    pub user: String,
+}
+
+fn redis_default_timeout() -> u64 {
+    constants::DEFAULT_REDIS_TIMEOUT
+}
+
+pub fn validate(cfg: &Redis) -> Result<(), Error> {
+    match Url::parse(&cfg.url) {
+        Ok(v) => {
+            if !["redis", "rediss", "unix", "redis+unix"].contains(&v.scheme()) {
+                return Err(Error::Config(format!(
+                    "invalid Redis URL {}, scheme must be redis, rediss or unix",
+                    cfg.url
+                )));
+            }
+        }
+        Err(e) => {
+            return Err(Error::Config(format!("invalid Redis URL: {}", e)));
+        }
+    };
+
+    if cfg.key.is_empty() {
+        return Err(Error::Config("empty key for Redis backend".to_string()));
+    }
+
+    if cfg.timeout == 0 {
+        return Err(Error::Config("invalid Redis timeout".to_string()));
+    }
+
+    Ok(())
+}
+
+// Credentials from the configuration override credentials in the URL
+fn connection_url(cfg: &Redis) -> Result<String, Error> {
+    let mut url = Url::parse(&cfg.url)?;
+    if !cfg.user.is_empty() && url.set_username(&cfg.user).is_err() {
+        return Err(Error::Config(format!("can't set user for {}", cfg.url)));
+    }
+    if !cfg.password.is_empty() && url.set_password(Some(&cfg.password)).is_err() {
+        return Err(Error::Config(format!("can't set password for {}", cfg.url)));
+    }
+    if cfg.insecure_ssl && url.scheme() == "rediss" {
+        url.set_fragment(Some("insecure"));
+    }
+    Ok(url.to_string())
+}
+
+enum Notification {
+    Subscribed,
+    Changed(String),
+    Failed(String),
+}
+
+// Key data of all hosts is stored in the hash <key>, indexed by host. The name of the host is
+// published on <channel> after every change, so subscribers only need to fetch the changed field.
+// Notifications are lost while disconnected, therefore the whole hash is read again after every
+// (re)connect
+pub struct RedisTransport {
+    cfg: Redis,
+    connection: Option<redis::Connection>,
+    notifications: Option<mpsc::Receiver<Notification>>,
+    stop: Arc<AtomicBool>,
+    subscribed: bool,
+    connected: bool,
+    hosts: BTreeSet<String>,
+    pending: VecDeque<Update>,
+}
+
+impl RedisTransport {
+    pub fn new(cfg: &Redis) -> Self {
+        RedisTransport {
+            cfg: cfg.clone(),
+            connection: None,
+            notifications: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            subscribed: false,
+            connected: false,
+            hosts: BTreeSet::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn channel(&self) -> String {
+        if self.cfg.channel.is_empty() {
+            self.cfg.key.clone()
+        } else {
+            self.cfg.channel.clone()
+        }
+    }
+
+    fn client(&self) -> Result<redis::Client, Error> {
+        let client = redis::Client::open(connection_url(&self.cfg)?)?;
+        Ok(client)
+    }
+
+    fn connection(&mut self) -> Result<&mut redis::Connection, Error> {
+        if self.connection.is_none() {
+            let timeout = Duration::from_secs(self.cfg.timeout);
+            debug!("connecting to {}", self.location());
+            let con = self.client()?.get_connection_with_timeout(timeout)?;
+            con.set_read_timeout(Some(timeout))?;
+            con.set_write_timeout(Some(timeout))?;
+            self.connection = Some(con);
+        }
+
+        match self.connection.as_mut() {
+            Some(v) => Ok(v),
+            None => panic!("BUG: no Redis connection - shouldn't happen"),
+        }
+    }
+
+    // The connection is dropped on errors and established again on the next command
+    fn query<T: redis::FromRedisValue>(&mut self, cmd: &redis::Cmd) -> Result<T, Error> {
+        let result = cmd.query::<T>(self.connection()?);
+        if result.is_err() {
+            self.connection = None;
+        }
+        Ok(result?)
+    }
+
+    fn fetch(&mut self) -> Result<BTreeMap<String, Vec<u8>>, Error> {
+        let mut cmd = redis::cmd("HGETALL");
+        cmd.arg(&self.cfg.key);
+        let data: HashMap<String, Vec<u8>> = self.query(&cmd)?;
+        Ok(data.into_iter().collect())
+    }
+
+    fn fetch_host(&mut self, host: &str) -> Result<Vec<u8>, Error> {
+        let mut cmd = redis::cmd("HGET");
+        cmd.arg(&self.cfg.key).arg(host);
+        let data: Option<Vec<u8>> = self.query(&cmd)?;
+        Ok(data.unwrap_or_default())
+    }
+
+    fn start_listener(&mut self) -> Result<(), Error> {
+        let client = self.client()?;
+        let channel = self.channel();
+        let (sender, receiver) = mpsc::channel::<Notification>();
+
+        self.stop = Arc::new(AtomicBool::new(false));
+        let stop = self.stop.clone();
+        let timeout = Duration::from_secs(self.cfg.timeout);
+        thread::spawn(move || listen(client, channel, timeout, sender, stop));
+
+        match receiver.recv_timeout(timeout) {
+            Ok(Notification::Subscribed) => {}
+            Ok(Notification::Failed(e)) => {
+                return Err(Error::Redis(format!(
+                    "can't subscribe to channel {}: {}",
+                    self.channel(),
+                    e
+                )));
+            }
+            _ => {
+                self.stop.store(true, Ordering::Relaxed);
+                return Err(Error::Redis(format!(
+                    "subscription to channel {} timed out",
+                    self.channel()
+                )));
+            }
+        };
+
+        self.notifications = Some(receiver);
+        Ok(())
+    }
+
+    fn stop_listener(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.notifications = None;
+    }
+
+    // Subscribe before reading the hash, so no change between both steps is lost
+    fn resync(&mut self) -> Result<(), Error> {
+        self.stop_listener();
+        self.start_listener()?;
+
+        let data = self.fetch()?;
+        info!(
+            "read key data of {} hosts from {}",
+            data.len(),
+            self.location()
+        );
+
+        self.pending.clear();
+        for host in self.hosts.iter() {
+            if !data.contains_key(host) {
+                self.pending
+                    .push_back(Update::Data(host.clone(), Vec::new()));
+            }
+        }
+        self.hosts = data.keys().cloned().collect();
+        for (host, payload) in data.into_iter() {
+            self.pending.push_back(Update::Data(host, payload));
+        }
+
+        Ok(())
+    }
+
+    fn disconnect(&mut self) {
+        self.stop_listener();
+        self.connection = None;
+        self.connected = false;
+    }
+}
+
+fn listen(
+    client: redis::Client,
+    channel: String,
+    timeout: Duration,
+    sender: mpsc::Sender<Notification>,
+    stop: Arc<AtomicBool>,
+) {
+    let mut con = match client.get_connection_with_timeout(timeout) {
+        Ok(v) => v,
+        Err(e) => {
+            let _ = sender.send(Notification::Failed(e.to_string()));
+            return;
+        }
+    };
+    let mut pubsub = con.as_pubsub();
+    if let Err(e) = pubsub.subscribe(&channel) {
+        let _ = sender.send(Notification::Failed(e.to_string()));
+        return;
+    }
+
+    // The read timeout is only used to check for the stop request
+    if let Err(e) =
+        pubsub.set_read_timeout(Some(Duration::from_millis(constants::REDIS_STOP_CHECK_MS)))
+    {
+        let _ = sender.send(Notification::Failed(e.to_string()));
+        return;
+    }
+
+    if sender.send(Notification::Subscribed).is_err() {
+        return;
+    }
+
+    while !stop.load(Ordering::Relaxed) {
+        let notification = match pubsub.get_message() {
+            Ok(msg) => match msg.get_payload::<String>() {
+                Ok(v) => Notification::Changed(v),
+                Err(e) => {
+                    warn!("can't parse notification on channel {}: {}", channel, e);
+                    continue;
+                }
+            },
+            Err(e) if e.is_timeout() => continue,
+            Err(e) => Notification::Failed(e.to_string()),
+        };
+        let failed = matches!(notification, Notification::Failed(_));
+        if sender.send(notification).is_err() || failed {
+            return;
+        }
+    }
+}
+
+impl Transport for RedisTransport {
+    // Credentials are not shown
+    fn location(&self) -> String {
+        let url = match Url::parse(&self.cfg.url) {
+            Ok(mut v) => {
+                let _ = v.set_password(None);
+                v.to_string()
+            }
+            Err(_) => self.cfg.url.clone(),
+        };
+        format!("Redis hash {} on {}", self.cfg.key, url)
+    }
+
+    fn publish(&mut self, data: &BTreeMap<String, Vec<u8>>) -> Result<(), Error> {
+        let channel = self.channel();
+        for (host, payload) in data.iter() {
+            let mut pipe = redis::pipe();
+            pipe.atomic();
+            if payload.is_empty() {
+                debug!(host = host; "deleting field {} of {}", host, self.location());
+                pipe.cmd("HDEL").arg(&self.cfg.key).arg(host).ignore();
+            } else {
+                debug!(host = host; "setting field {} of {}", host, self.location());
+                pipe.cmd("HSET")
+                    .arg(&self.cfg.key)
+                    .arg(host)
+                    .arg(payload)
+                    .ignore();
+            }
+            pipe.cmd("PUBLISH").arg(&channel).arg(host).ignore();
+
+            let result = pipe.query::<()>(self.connection()?);
+            if let Err(e) = result {
+                self.connection = None;
+                return Err(Error::Redis(format!(
+                    "can't store key data of {}: {}",
+                    host, e
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, hosts: &[String]) -> Result<(), Error> {
+        let data: BTreeMap<String, Vec<u8>> =
+            hosts.iter().map(|h| (h.clone(), Vec::new())).collect();
+        self.publish(&data)
+    }
+
+    // All data is read at once, there is no need to wait for further data
+    fn list(&mut self, _wait: Duration) -> Result<BTreeMap<String, Vec<u8>>, Error> {
+        self.fetch()
+    }
+
+    fn subscribe(&mut self) -> Result<(), Error> {
+        info!("subscribing to key data on {}", self.location());
+        self.subscribed = true;
+        self.connected = false;
+        self.hosts.clear();
+        self.pending.clear();
+        Ok(())
+    }
+
+    fn poll(&mut self, timeout: Duration) -> Result<Option<Update>, Error> {
+        if !self.subscribed {
+            return Err(Error::Redis(format!(
+                "not subscribed to {}",
+                self.location()
+            )));
+        }
+
+        if !self.connected {
+            if let Err(e) = self.resync() {
+                warn!("{}, retrying", e);
+                self.disconnect();
+                thread::sleep(timeout);
+                return Ok(None);
+            }
+            info!(
+                "subscribed to channel {} on {}",
+                self.channel(),
+                self.location()
+            );
+            self.connected = true;
+            return Ok(Some(Update::Connected));
+        }
+
+        if let Some(v) = self.pending.pop_front() {
+            return Ok(Some(v));
+        }
+
+        let notification = match &self.notifications {
+            Some(v) => v.recv_timeout(timeout),
+            None => Err(mpsc::RecvTimeoutError::Disconnected),
+        };
+
+        match notification {
+            Ok(Notification::Changed(host)) => {
+                debug!(host = host; "change of key data for {} announced", host);
+                match self.fetch_host(&host) {
+                    Ok(payload) => {
+                        if payload.is_empty() {
+                            self.hosts.remove(&host);
+                        } else {
+                            self.hosts.insert(host.clone());
+                        }
+                        Ok(Some(Update::Data(host, payload)))
+                    }
+                    Err(e) => {
+                        warn!("can't fetch key data of {}: {}, reconnecting", host, e);
+                        self.disconnect();
+                        Ok(Some(Update::Disconnected))
+                    }
+                }
+            }
+            Ok(Notification::Subscribed) => Ok(None),
+            Ok(Notification::Failed(e)) => {
+                warn!(
+                    "connection to {} was lost: {}, reconnecting",
+                    self.location(),
+                    e
+                );
+                self.disconnect();
+                Ok(Some(Update::Disconnected))
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => Ok(None),
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                warn!("connection to {} was lost, reconnecting", self.location());
+                self.disconnect();
+                Ok(Some(Update::Disconnected))
+            }
+        }
+    }
+
+    fn close(&mut self) {
+        self.stop_listener();
+        self.connection = None;
+        self.subscribed = false;
+        self.connected = false;
+        self.hosts.clear();
+        self.pending.clear();
+    }
+}
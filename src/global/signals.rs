@@ -0,0 +1,49 @@
+use crate::error::Error;
+
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM, SIGUSR1};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// SIGTERM and SIGINT request termination, SIGHUP a reload of the configuration and SIGUSR1 a
+// rewrite of the output. Tools without output to rewrite ignore SIGUSR1
+pub struct Signals {
+    terminate: Arc<AtomicBool>,
+    reload: Arc<AtomicBool>,
+    rewrite: Arc<AtomicBool>,
+}
+
+impl Signals {
+    pub fn register() -> Result<Self, Error> {
+        let result = Signals {
+            terminate: Arc::new(AtomicBool::new(false)),
+            reload: Arc::new(AtomicBool::new(false)),
+            rewrite: Arc::new(AtomicBool::new(false)),
+        };
+
+        for sig in [SIGTERM, SIGINT] {
+            // A second signal terminates immediately, e.g. if connecting to the broker hangs
+            signal_hook::flag::register_conditional_shutdown(
+                sig,
+                1,
+                Arc::clone(&result.terminate),
+            )?;
+            signal_hook::flag::register(sig, Arc::clone(&result.terminate))?;
+        }
+        signal_hook::flag::register(SIGHUP, Arc::clone(&result.reload))?;
+        signal_hook::flag::register(SIGUSR1, Arc::clone(&result.rewrite))?;
+
+        Ok(result)
+    }
+
+    pub fn terminate(&self) -> bool {
+        self.terminate.load(Ordering::Relaxed)
+    }
+
+    pub fn reload(&self) -> bool {
+        self.reload.swap(false, Ordering::Relaxed)
+    }
+
+    pub fn rewrite(&self) -> bool {
+        self.rewrite.swap(false, Ordering::Relaxed)
+    }
+}
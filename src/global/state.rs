@@ -0,0 +1,197 @@
+use crate::constants;
+use crate::error::Error;
+use crate::payload::Message;
+
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Change {
+    Added,
+    Changed,
+    Removed,
+    Unchanged,
+}
+
+// Current key data of all hosts, indexed by the host name from the MQTT topic
+#[derive(Clone, Debug)]
+pub struct KeyMap {
+    hosts: HashMap<String, Message>,
+    max_payload_size: usize,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        KeyMap::new()
+    }
+}
+
+impl KeyMap {
+    pub fn new() -> Self {
+        KeyMap {
+            hosts: HashMap::new(),
+            max_payload_size: constants::DEFAULT_MAX_PAYLOAD_SIZE,
+        }
+    }
+
+    // Larger payloads, compressed or decompressed, are rejected before parsing
+    pub fn set_max_payload_size(&mut self, size: usize) {
+        self.max_payload_size = size;
+    }
+
+    // An empty payload or an empty key list removes the host. Unparseable payloads are
+    // reported as Error::Payload, invalid key data as Error::Validation
+    pub fn update(&mut self, host: &str, payload: &[u8]) -> Result<Change, Error> {
+        if payload.is_empty() {
+            self.hosts.remove(host);
+            return Ok(Change::Removed);
+        }
+
+        let msg = Message::parse_limited(payload, self.max_payload_size)?;
+        msg.validate()?;
+        Ok(self.insert(host, msg))
+    }
+
+    pub fn insert(&mut self, host: &str, msg: Message) -> Change {
+        if msg.keys.is_empty() {
+            self.hosts.remove(host);
+            return Change::Removed;
+        }
+
+        // The publication timestamp is stored, but doesn't count as a change
+        let change = match self.hosts.get(host) {
+            Some(v) if v.same_key_data(&msg) => Change::Unchanged,
+            Some(_) => Change::Changed,
+            None => Change::Added,
+        };
+        self.hosts.insert(host.to_string(), msg);
+        change
+    }
+
+    pub fn remove(&mut self, host: &str) -> Option<Message> {
+        self.hosts.remove(host)
+    }
+
+    pub fn clear(&mut self) {
+        self.hosts.clear();
+    }
+
+    pub fn get(&self, host: &str) -> Option<&Message> {
+        self.hosts.get(host)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Message)> {
+        self.hosts.iter()
+    }
+
+    pub fn hosts(&self) -> usize {
+        self.hosts.len()
+    }
+
+    pub fn keys(&self) -> usize {
+        self.hosts.values().map(|v| v.keys.len()).sum()
+    }
+
+    // Key data without publication timestamp never expires
+    pub fn expire(&mut self, max_age: u64, now: i64) -> Vec<(String, Message)> {
+        let expired: Vec<String> = self
+            .hosts
+            .iter()
+            .filter(|(_, msg)| match msg.published {
+                Some(v) => now - v > max_age as i64,
+                None => false,
+            })
+            .map(|(host, _)| host.clone())
+            .collect();
+
+        expired
+            .into_iter()
+            .filter_map(|host| self.hosts.remove(&host).map(|msg| (host, msg)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(key: &str, published: i64) -> Vec<u8> {
+        format!(
+            r#"{{"version":"1.1","hostname":["node01"],"keys":[{{"type":"ssh-ed25519","key":"{}","comment":"root@node01"}}],"published":{}}}"#,
+            key, published
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn update_reports_changes() {
+        let mut map = KeyMap::new();
+        assert_eq!(
+            map.update("node01", &payload("AAAA", 100)).unwrap(),
+            Change::Added
+        );
+        assert_eq!(
+            map.update("node01", &payload("AAAA", 200)).unwrap(),
+            Change::Unchanged
+        );
+        assert_eq!(map.get("node01").unwrap().published, Some(200));
+        assert_eq!(
+            map.update("node01", &payload("BBBB", 300)).unwrap(),
+            Change::Changed
+        );
+        assert_eq!(map.hosts(), 1);
+        assert_eq!(map.keys(), 1);
+    }
+
+    #[test]
+    fn update_removes_host() {
+        let mut map = KeyMap::new();
+        map.update("node01", &payload("AAAA", 100)).unwrap();
+        assert_eq!(map.update("node01", b"").unwrap(), Change::Removed);
+        assert!(map.get("node01").is_none());
+
+        map.update("node01", &payload("AAAA", 100)).unwrap();
+        let empty = br#"{"hostname":["node01"],"keys":[]}"#;
+        assert_eq!(map.update("node01", empty).unwrap(), Change::Removed);
+        assert!(map.get("node01").is_none());
+        assert!(map.remove("node01").is_none());
+    }
+
+    #[test]
+    fn update_rejects_invalid_key_data() {
+        let mut map = KeyMap::new();
+        map.update("node01", &payload("AAAA", 100)).unwrap();
+
+        let invalid = br#"{"hostname":["node 01"],"keys":[{"type":"ssh-ed25519","key":"BBBB","comment":""}]}"#;
+        assert!(matches!(
+            map.update("node01", invalid),
+            Err(Error::Validation(_))
+        ));
+        assert!(matches!(
+            map.update("node01", b"garbage"),
+            Err(Error::Payload(_))
+        ));
+        map.set_max_payload_size(10);
+        assert!(matches!(
+            map.update("node01", &payload("BBBB", 200)),
+            Err(Error::Validation(_))
+        ));
+
+        assert_eq!(map.get("node01").unwrap().keys[0].key, "AAAA");
+    }
+
+    #[test]
+    fn expire_removes_old_key_data() {
+        let mut map = KeyMap::new();
+        map.update("old", &payload("AAAA", 100)).unwrap();
+        map.update("new", &payload("BBBB", 1000)).unwrap();
+        let mut msg = Message::parse(&payload("CCCC", 0)).unwrap();
+        msg.published = None;
+        map.insert("unknown", msg);
+
+        let expired = map.expire(500, 1100);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].0, "old");
+        assert_eq!(map.hosts(), 2);
+        assert!(map.get("unknown").is_some());
+    }
+}
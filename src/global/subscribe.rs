@@ -0,0 +1,176 @@
+use crate::constants;
+use crate::error::Error;
+use crate::signals::Signals;
+use crate::transport::{self, Backend, Transport, Update};
+
+use log::info;
+use std::time::{Duration, Instant};
+
+// Processing of the key data received by a subscription to the backend
+pub trait Handler {
+    // Backend to subscribe to, used again for each reconnect
+    fn backend(&self) -> &Backend;
+    // Received key data and changes of the connection state
+    fn update(&mut self, update: Update, location: &str) -> Result<(), Error>;
+    // Reload of the configuration requested, returns true if the backend must be subscribed again
+    fn reload(&mut self) -> Result<bool, Error>;
+    // Rewrite of the output requested
+    fn rewrite(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+    // Termination requested, the subscription is already closed
+    fn shutdown(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+    // Called after each poll of the backend, at least once per poll interval
+    fn tick(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+// The initial transfer of key data after connecting to the backend is finished if no key data
+// arrived for the quiet period, at the latest after the maximal period. It doesn't depend on
+// key data at all, e.g. for an empty backend or a persistent MQTT session
+#[derive(Debug, Default)]
+pub struct InitialSync {
+    deadline: Option<Instant>,
+    connected_since: Option<Instant>,
+    done: bool,
+}
+
+impl InitialSync {
+    pub fn new() -> Self {
+        InitialSync::default()
+    }
+
+    pub fn connected(&mut self) {
+        let now = Instant::now();
+        self.connected_since = Some(now);
+        if !self.done {
+            self.deadline = Some(now + quiet_period());
+        }
+    }
+
+    pub fn received(&mut self) {
+        if let (Some(v), Some(since)) = (self.deadline, self.connected_since) {
+            let max = since + Duration::from_secs(constants::INITIAL_SYNC_MAX_PERIOD);
+            if v < max {
+                self.deadline = Some(max.min(Instant::now() + quiet_period()));
+            }
+        }
+    }
+
+    // All key data was removed and must be transferred again after the next connect
+    pub fn restart(&mut self) {
+        self.done = false;
+        self.deadline = None;
+    }
+
+    pub fn done(&self) -> bool {
+        self.done
+    }
+
+    // Time until the transfer is considered finished, if it is running
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|v| v.saturating_duration_since(Instant::now()))
+    }
+
+    // True once when the transfer has finished
+    pub fn finished(&mut self) -> bool {
+        if self.deadline.is_some_and(|v| Instant::now() >= v) {
+            self.deadline = None;
+            self.done = true;
+            return true;
+        }
+        false
+    }
+}
+
+fn quiet_period() -> Duration {
+    Duration::from_secs(constants::INITIAL_SYNC_QUIET_PERIOD)
+}
+
+enum Outcome {
+    Reconnect,
+    Shutdown,
+}
+
+// Passes all updates of the backend to the handler until termination is requested
+pub fn run(handler: &mut dyn Handler, signals: &Signals) -> Result<(), Error> {
+    loop {
+        let mut transport = transport::new(handler.backend());
+        transport.subscribe()?;
+
+        let outcome = consume(handler, &mut *transport, signals);
+
+        transport.close();
+
+        match outcome? {
+            Outcome::Reconnect => {
+                handler.update(Update::Disconnected, &transport.location())?;
+                crate::systemd::notify_or_warn(&format!(
+                    "STATUS=reconnecting to {}",
+                    transport.location()
+                ));
+            }
+            Outcome::Shutdown => {
+                crate::systemd::notify_or_warn("STOPPING=1");
+                return handler.shutdown();
+            }
+        }
+    }
+}
+
+fn consume(
+    handler: &mut dyn Handler,
+    transport: &mut dyn Transport,
+    signals: &Signals,
+) -> Result<Outcome, Error> {
+    // Ping the watchdog at half of the interval requested by systemd
+    let watchdog = crate::systemd::watchdog_interval().map(|v| v / 2);
+    let poll_interval = Duration::from_millis(constants::SUBSCRIBE_POLL_INTERVAL_MS);
+    let timeout = match watchdog {
+        Some(v) if v < poll_interval => v,
+        _ => poll_interval,
+    };
+    let mut last_ping = Instant::now();
+
+    loop {
+        let update = transport.poll(timeout)?;
+
+        if let Some(interval) = watchdog {
+            if last_ping.elapsed() >= interval {
+                crate::systemd::notify_or_warn("WATCHDOG=1");
+                last_ping = Instant::now();
+            }
+        }
+
+        if signals.terminate() {
+            info!("termination requested, shutting down");
+            return Ok(Outcome::Shutdown);
+        }
+
+        if signals.rewrite() {
+            handler.rewrite()?;
+        }
+
+        if signals.reload() && handler.reload()? {
+            return Ok(Outcome::Reconnect);
+        }
+
+        match update {
+            Some(Update::Disconnected) => {
+                handler.update(Update::Disconnected, &transport.location())?;
+                crate::systemd::notify_or_warn(&format!(
+                    "STATUS=reconnecting to {}",
+                    transport.location()
+                ));
+            }
+            Some(v) => handler.update(v, &transport.location())?,
+            None => {}
+        };
+
+        handler.tick()?;
+    }
+}
@@ -0,0 +1,49 @@
+use crate::error::Error;
+
+use log::warn;
+use std::env;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::Duration;
+
+// Send a state change to the service manager, a no-op if not started by systemd with Type=notify
+pub fn notify(state: &str) -> Result<(), Error> {
+    let socket_path = match env::var("NOTIFY_SOCKET") {
+        Ok(v) => v,
+        Err(_) => return Ok(()),
+    };
+
+    let address = match socket_path.strip_prefix('@') {
+        Some(v) => SocketAddr::from_abstract_name(v.as_bytes())?,
+        None => SocketAddr::from_pathname(&socket_path)?,
+    };
+
+    let socket = UnixDatagram::unbound()?;
+    socket.send_to_addr(state.as_bytes(), &address)?;
+    Ok(())
+}
+
+// A failed notification must not stop a service, so it's only logged
+pub fn notify_or_warn(state: &str) {
+    if let Err(e) = notify(state) {
+        warn!("can't notify systemd: {}", e);
+    }
+}
+
+pub fn watchdog_interval() -> Option<Duration> {
+    let usec = match env::var("WATCHDOG_USEC") {
+        Ok(v) => v.parse::<u64>().ok()?,
+        Err(_) => return None,
+    };
+
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok()? != std::process::id() {
+            return None;
+        }
+    }
+
+    if usec == 0 {
+        return None;
+    }
+    Some(Duration::from_micros(usec))
+}
@@ -0,0 +1,123 @@
+use crate::error::Error;
+use crate::etcd;
+use crate::filesystem;
+use crate::http;
+use crate::mqtt;
+use crate::redis;
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+#[derive(Debug, PartialEq)]
+pub enum Update {
+    // (Re-)established connection, all current key data will be received again
+    Connected,
+    // Connection was lost, the next poll tries to reconnect
+    Disconnected,
+    // Key data for a host, an empty payload removes the host
+    Data(String, Vec<u8>),
+}
+
+// Storage and distribution of key data, indexed by host. All key data must be persistent
+// and must be sent to new subscribers
+pub trait Transport {
+    // Human readable location of the key data for log messages
+    fn location(&self) -> String;
+
+    fn publish(&mut self, data: &BTreeMap<String, Vec<u8>>) -> Result<(), Error>;
+
+    fn delete(&mut self, hosts: &[String]) -> Result<(), Error>;
+
+    // Current key data, wait is the time to wait for further data
+    fn list(&mut self, wait: Duration) -> Result<BTreeMap<String, Vec<u8>>, Error>;
+
+    fn subscribe(&mut self) -> Result<(), Error>;
+
+    // Returns None if nothing happened within timeout
+    fn poll(&mut self, timeout: Duration) -> Result<Option<Update>, Error>;
+
+    fn close(&mut self);
+}
+
+// Configuration of the backends, exactly one backend must be configured
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct Backend {
+    pub etcd: Option<etcd::Etcd>,
+    pub filesystem: Option<filesystem::Filesystem>,
+    pub http: Option<http::Http>,
+    pub mqtt: Option<mqtt::MQTT>,
+    pub redis: Option<redis::Redis>,
+}
+
+impl Backend {
+    pub fn validate(&self) -> Result<(), Error> {
+        let configured = [
+            self.etcd.is_some(),
+            self.filesystem.is_some(),
+            self.http.is_some(),
+            self.mqtt.is_some(),
+            self.redis.is_some(),
+        ]
+        .iter()
+        .filter(|v| **v)
+        .count();
+        match configured {
+            0 => Err(Error::Config(
+                "no backend configured, one of etcd, filesystem, http, mqtt or redis is required"
+                    .to_string(),
+            )),
+            1 => {
+                if let Some(v) = &self.etcd {
+                    etcd::validate(v)?;
+                }
+                if let Some(v) = &self.filesystem {
+                    filesystem::validate(v)?;
+                }
+                if let Some(v) = &self.http {
+                    http::validate(v)?;
+                }
+                if let Some(v) = &self.redis {
+                    redis::validate(v)?;
+                }
+                Ok(())
+            }
+            _ => Err(Error::Config(
+                "only one of etcd, filesystem, http, mqtt or redis can be configured".to_string(),
+            )),
+        }
+    }
+
+    // Without a configured client ID, a random MQTT client ID is generated for every parsed
+    // configuration
+    pub fn changed(&self, new: &Backend) -> bool {
+        let mut new = new.clone();
+        if let (Some(old_mqtt), Some(new_mqtt)) = (&self.mqtt, &mut new.mqtt) {
+            new_mqtt.client_id = old_mqtt.client_id.clone();
+        }
+        *self != new
+    }
+
+    pub fn location(&self) -> String {
+        new(self).location()
+    }
+}
+
+pub fn new(cfg: &Backend) -> Box<dyn Transport + Send> {
+    if let Some(v) = &cfg.etcd {
+        return Box::new(etcd::EtcdTransport::new(v));
+    }
+    if let Some(v) = &cfg.filesystem {
+        return Box::new(filesystem::FilesystemTransport::new(v));
+    }
+    if let Some(v) = &cfg.http {
+        return Box::new(http::HttpTransport::new(v));
+    }
+    if let Some(v) = &cfg.mqtt {
+        return Box::new(mqtt::MqttTransport::new(v));
+    }
+    if let Some(v) = &cfg.redis {
+        return Box::new(redis::RedisTransport::new(v));
+    }
+    panic!("BUG: no backend configured - shouldn't happen");
+}
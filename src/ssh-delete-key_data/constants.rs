@@ -1,2 +1,2 @@
 pub const DEFAULT_CONFIG_FILE: &str = "/etc/ssh-delete-key_data.yaml";
-pub const EMPTY_MESSAGE: [u8; 0] = [];
+pub const DEFAULT_WAIT: u64 = 5;
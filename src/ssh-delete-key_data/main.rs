@@ -1,10 +1,14 @@
 mod config;
 mod constants;
-mod mqtt;
+mod prompt;
+mod remove;
+mod select;
+mod stale;
 mod usage;
 
 use getopts::Options;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
+use std::time::Duration;
 use std::{env, process};
 
 fn main() {
@@ -14,7 +18,14 @@ fn main() {
 
     options.optflag("C", "check", "Check configuration file and exit");
     options.optflag("D", "debug", "Enable debug output");
+    options.optflag("E", "regex", "Hosts are regular expressions");
     options.optflag("V", "version", "Show version information");
+    options.optopt(
+        "f",
+        "file",
+        "Read list of hosts from file, - for standard input",
+        "<file>",
+    );
     options.optflag("h", "help", "Show help text");
     options.optopt(
         "c",
@@ -22,7 +33,28 @@ fn main() {
         "Configuration file",
         constants::DEFAULT_CONFIG_FILE,
     );
+    options.optopt(
+        "L",
+        "log-format",
+        "Log format: text, json, syslog or journald",
+        "text",
+    );
+    options.optflag("p", "pattern", "Hosts are glob patterns");
     options.optflag("q", "quiet", "Quiet operation");
+    options.optflag("r", "remove", "Remove key data of stale hosts");
+    options.optopt(
+        "s",
+        "stale",
+        "Report hosts which haven't published key data within <age>",
+        "<age>",
+    );
+    options.optopt(
+        "w",
+        "wait",
+        "Wait for retained messages",
+        &constants::DEFAULT_WAIT.to_string(),
+    );
+    options.optflag("y", "yes", "Don't ask for confirmation");
 
     let opts = match options.parse(&argv[1..]) {
         Ok(v) => v,
@@ -30,7 +62,7 @@ fn main() {
             eprintln!("Error: Can't parse command line arguments: {}", e);
             println!();
             usage::show_usage();
-            process::exit(1);
+            process::exit(global::constants::EXIT_USAGE_ERROR);
         }
     };
 
@@ -57,20 +89,43 @@ fn main() {
         None => constants::DEFAULT_CONFIG_FILE.to_string(),
     };
 
-    // XXX: Initialisation of logging should never fail
-    global::logging::init(log_level).unwrap();
+    let log_format = match opts.opt_str("L") {
+        Some(v) => match v.parse::<global::logging::Format>() {
+            Ok(v) => Some(v),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                println!();
+                usage::show_usage();
+                process::exit(global::constants::EXIT_USAGE_ERROR);
+            }
+        },
+        None => None,
+    };
 
     let configuration = match config::parse_config_file(&config_file) {
         Ok(v) => v,
         Err(e) => {
+            global::logging::init_or_exit(
+                log_level,
+                &global::logging::Logging::default(),
+                log_format,
+                global::logging::Output::Stderr,
+            );
             error!(
                 "parsing of configuration file {} failed: {}",
                 config_file, e
             );
-            process::exit(1);
+            process::exit(e.exit_code());
         }
     };
 
+    global::logging::init_or_exit(
+        log_level,
+        &configuration.logging,
+        log_format,
+        global::logging::Output::Stderr,
+    );
+
     if opts.opt_present("C") {
         info!("configuration file {} is valid", config_file);
         process::exit(0);
@@ -78,22 +133,137 @@ fn main() {
 
     debug!("parsed configuration: {:?}", configuration);
 
-    if opts.free.is_empty() {
+    let wait = match opts.opt_str("w") {
+        Some(v) => match v.parse::<u64>() {
+            Ok(v) => Duration::from_secs(v),
+            Err(e) => {
+                error!("can't parse wait time {}: {}", v, e);
+                process::exit(global::constants::EXIT_USAGE_ERROR);
+            }
+        },
+        None => Duration::from_secs(constants::DEFAULT_WAIT),
+    };
+
+    if let Some(v) = opts.opt_str("s") {
+        let max_age = match global::duration::parse(&v) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("{}", e);
+                process::exit(global::constants::EXIT_USAGE_ERROR);
+            }
+        };
+
+        let hosts = match stale::find(&configuration, max_age, wait) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("can't fetch key data: {}", e);
+                process::exit(e.exit_code());
+            }
+        };
+
+        if hosts.is_empty() {
+            info!("no stale hosts found");
+            process::exit(0);
+        }
+
+        stale::print(&hosts);
+
+        if opts.opt_present("r") {
+            let mut remove: Vec<String> = Vec::new();
+            for h in hosts.into_iter() {
+                match h.published {
+                    Some(_) => remove.push(h.host),
+                    None => warn!(
+                        host = h.host;
+                        "{} has no publication timestamp, not removing key data", h.host
+                    ),
+                };
+            }
+
+            if remove.is_empty() {
+                process::exit(0);
+            }
+
+            if !opts.opt_present("y")
+                && !prompt::confirm(&format!("Remove key data of {} hosts?", remove.len()))
+            {
+                info!("removal of stale hosts cancelled");
+                process::exit(0);
+            }
+
+            if let Err(e) = remove::delete(&configuration, remove, wait) {
+                error!("removal of key data failed: {}", e);
+                process::exit(e.exit_code());
+            }
+        }
+
+        process::exit(0);
+    }
+
+    let mut hosts = opts.free.clone();
+    if let Some(v) = opts.opt_str("f") {
+        match select::read_host_list(&v) {
+            Ok(v) => hosts.extend(v),
+            Err(e) => {
+                error!("{}", e);
+                process::exit(e.exit_code());
+            }
+        };
+    }
+
+    if hosts.is_empty() {
         error!("Missing list of hosts");
         usage::show_usage();
-        process::exit(1);
+        process::exit(global::constants::EXIT_USAGE_ERROR);
     }
 
-    for host in opts.free.iter() {
-        if let Err(e) = config::validate_hostname(host) {
-            error!("{}", e);
-            process::exit(1);
+    let patterns = opts.opt_present("p") || opts.opt_present("E");
+    if patterns {
+        let mut matchers: Vec<global::matcher::Matcher> = Vec::new();
+        for pattern in hosts.iter() {
+            match global::matcher::Matcher::new(pattern, opts.opt_present("E")) {
+                Ok(v) => matchers.push(v),
+                Err(e) => {
+                    error!("{}", e);
+                    process::exit(global::constants::EXIT_USAGE_ERROR);
+                }
+            };
+        }
+
+        hosts = match select::resolve(&configuration, &matchers, wait) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("can't fetch key data: {}", e);
+                process::exit(e.exit_code());
+            }
+        };
+
+        if hosts.is_empty() {
+            info!("no hosts with key data match the patterns");
+            process::exit(0);
+        }
+    } else {
+        for host in hosts.iter() {
+            if let Err(e) = config::validate_hostname(host) {
+                error!("{}: {}", e, host);
+                process::exit(e.exit_code());
+            }
+        }
+    }
+
+    if (patterns || opts.opt_present("f")) && !opts.opt_present("y") {
+        for host in hosts.iter() {
+            println!("{}", host);
+        }
+        if !prompt::confirm(&format!("Remove key data of {} hosts?", hosts.len())) {
+            info!("removal of key data cancelled");
+            process::exit(0);
         }
     }
 
-    if let Err(e) = mqtt::send(&configuration, opts.free) {
-        error!("MQTT operation failed: {}", e);
-        process::exit(1);
+    if let Err(e) = remove::delete(&configuration, hosts, wait) {
+        error!("removal of key data failed: {}", e);
+        process::exit(e.exit_code());
     }
 
     process::exit(0);
@@ -0,0 +1,25 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+
+// If the host list was read from standard input, the answer must be read from the terminal
+pub fn confirm(question: &str) -> bool {
+    let mut answer = String::new();
+    print!("{} [y/N] ", question);
+    if io::stdout().flush().is_err() {
+        return false;
+    }
+
+    let result = if io::stdin().is_terminal() {
+        io::stdin().lock().read_line(&mut answer)
+    } else {
+        match File::open("/dev/tty") {
+            Ok(v) => BufReader::new(v).read_line(&mut answer),
+            Err(_) => io::stdin().lock().read_line(&mut answer),
+        }
+    };
+    if result.is_err() {
+        return false;
+    }
+
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
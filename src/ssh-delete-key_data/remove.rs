@@ -0,0 +1,69 @@
+use crate::config;
+
+use global::error::Error;
+use log::{error, info, warn};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+// Only hosts with stored key data are removed and the removal is confirmed by reading the stored
+// data again
+pub fn delete(
+    cfg: &config::Configuration,
+    hostlist: Vec<String>,
+    wait: Duration,
+) -> Result<(), Error> {
+    let mut transport = global::transport::new(&cfg.backend);
+    let existing = transport.list(wait)?;
+    let mut remove: Vec<String> = Vec::new();
+    for host in hostlist.into_iter() {
+        if existing.contains_key(&host) {
+            remove.push(host);
+        } else {
+            warn!(host = host; "no key data found for {}, skipping", host);
+        }
+    }
+
+    if remove.is_empty() {
+        warn!(
+            "none of the requested hosts has key data on {}",
+            transport.location()
+        );
+        return Ok(());
+    }
+
+    for host in remove.iter() {
+        info!(host = host; "removing key data of {} from {}", host, transport.location());
+    }
+    transport.delete(&remove)?;
+
+    let remaining = transport.list(wait)?;
+    let mut failed: Vec<String> = Vec::new();
+    for host in remove.into_iter() {
+        if remaining.contains_key(&host) {
+            error!(
+                host = host;
+                "key data for {} is still present on {}", host, transport.location()
+            );
+            failed.push(host);
+        } else {
+            info!(host = host; "removal of key data for {} confirmed", host);
+        }
+    }
+
+    if !failed.is_empty() {
+        return Err(Error::Backend(format!(
+            "removal of key data could not be confirmed for {}",
+            failed.join(", ")
+        )));
+    }
+
+    Ok(())
+}
+
+// Stored key data, indexed by host
+pub fn fetch(
+    cfg: &config::Configuration,
+    wait: Duration,
+) -> Result<BTreeMap<String, Vec<u8>>, Error> {
+    global::transport::new(&cfg.backend).list(wait)
+}
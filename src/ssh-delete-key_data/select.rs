@@ -0,0 +1,49 @@
+use crate::config;
+use crate::remove;
+
+use global::error::Error;
+use global::matcher::Matcher;
+use log::debug;
+use std::fs;
+use std::io::{self, Read};
+use std::time::Duration;
+
+// One host per line, empty lines and lines starting with # are ignored. - reads from standard input
+pub fn read_host_list(file: &str) -> Result<Vec<String>, Error> {
+    let raw = if file == "-" {
+        let mut raw = String::new();
+        io::stdin().read_to_string(&mut raw)?;
+        raw
+    } else {
+        match fs::read_to_string(file) {
+            Ok(v) => v,
+            Err(e) => {
+                return Err(Error::Io(format!("can't read {}: {}", file, e)));
+            }
+        }
+    };
+
+    Ok(raw
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| l.to_string())
+        .collect())
+}
+
+pub fn resolve(
+    cfg: &config::Configuration,
+    matchers: &[Matcher],
+    wait: Duration,
+) -> Result<Vec<String>, Error> {
+    let mut result: Vec<String> = Vec::new();
+
+    for host in remove::fetch(cfg, wait)?.into_keys() {
+        if matchers.iter().any(|m| m.matches(&host)) {
+            debug!(host = host; "{} matches host pattern", host);
+            result.push(host);
+        }
+    }
+
+    Ok(result)
+}
@@ -0,0 +1,93 @@
+use crate::config;
+use crate::remove;
+
+use global::encryption::Identities;
+use global::error::Error;
+use log::{debug, warn};
+use std::time::Duration;
+
+pub struct Host {
+    pub host: String,
+    pub published: Option<i64>,
+}
+
+// Hosts without a publication timestamp are reported too, their age can't be determined.
+// Key data which can't be decrypted is an error, the host could be reported as stale and removed
+pub fn find(cfg: &config::Configuration, max_age: u64, wait: Duration) -> Result<Vec<Host>, Error> {
+    let mut result: Vec<Host> = Vec::new();
+    let now = chrono::Utc::now().timestamp();
+    let identities = match &cfg.payload.identity_file {
+        Some(v) => Some(Identities::from_file(v)?),
+        None => None,
+    };
+
+    for (host, payload) in remove::fetch(cfg, wait)?.into_iter() {
+        let payload = match global::encryption::decrypt(
+            &payload,
+            identities.as_ref(),
+            global::constants::DEFAULT_MAX_PAYLOAD_SIZE,
+        ) {
+            Ok(v) => v,
+            Err(e) => {
+                return Err(Error::Payload(format!(
+                    "can't read key data of {}: {}",
+                    host, e
+                )));
+            }
+        };
+        let msg = match global::payload::Message::parse(&payload) {
+            Ok(v) => v,
+            Err(e) => {
+                warn!(host = host; "can't parse key data of {}: {}", host, e);
+                continue;
+            }
+        };
+
+        match msg.published {
+            Some(v) if now - v <= max_age as i64 => {
+                debug!(host = host; "{} published key data {} seconds ago", host, now - v);
+            }
+            _ => result.push(Host {
+                host,
+                published: msg.published,
+            }),
+        };
+    }
+
+    Ok(result)
+}
+
+pub fn print(hosts: &[Host]) {
+    let now = chrono::Utc::now().timestamp();
+    let width = hosts
+        .iter()
+        .map(|h| h.host.len())
+        .max()
+        .unwrap_or_default()
+        .max("HOST".len());
+
+    println!("{:<w$}  {:<25}  AGE", "HOST", "LAST PUBLISHED", w = width);
+    for h in hosts.iter() {
+        let (published, age) = match h
+            .published
+            .and_then(|v| chrono::DateTime::from_timestamp(v, 0))
+        {
+            Some(v) => (v.to_rfc3339(), format_age(now - v.timestamp())),
+            None => ("unknown".to_string(), "unknown".to_string()),
+        };
+        println!("{:<w$}  {:<25}  {}", h.host, published, age, w = width);
+    }
+}
+
+fn format_age(seconds: i64) -> String {
+    let days = seconds / 86400;
+    let hours = (seconds % 86400) / 3600;
+    let minutes = (seconds % 3600) / 60;
+    if days > 0 {
+        format!("{}d{}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h{}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
@@ -3,17 +3,41 @@ use crate::constants;
 pub fn show_usage() {
     global::usage::show_version();
     println!(
-        "Usage: {} [-c <cfg>|--config=<cfg>] [-h|--help] [-q|--quiet] [-C|--check] [-D|--debug] [-V|--version] <host> ...
+        "Usage: {} [-c <cfg>|--config=<cfg>] [-h|--help] [-L <fmt>|--log-format=<fmt>] [-q|--quiet] [-C|--check] [-D|--debug] [-V|--version] [-E|--regex] [-f <file>|--file=<file>] [-p|--pattern] [-w <sec>|--wait=<sec>] [-y|--yes] <host> ...\n       {} [options] -s <age>|--stale=<age> [-r|--remove] [-w <sec>|--wait=<sec>] [-y|--yes]
 
     -c <cfg>                    Read configuration from file <cfg>
     --config=<cfg>              Default: {}
 
+    -E                          Hosts are regular expressions, matched against the hosts
+    --regex                     with stored key data
+
+    -f <file>                   Read list of hosts from <file>, one host per line.
+    --file=<file>               Use - to read the list from standard input
+
     -h                          Shows this text
     --help
 
+    -L <fmt>                    Log format, one of text, json, syslog or journald
+    --log-format=<fmt>          Overrides the log format from the configuration file
+
+    -p                          Hosts are glob patterns, matched against the hosts
+    --pattern                   with stored key data
+
     -q                          Quiet operation.
     --quiet                     Only warning and critical messages will be logged
 
+    -r                          Remove key data of stale hosts after confirmation
+    --remove
+
+    -s <age>                    Report hosts which haven't published key data within <age>
+    --stale=<age>               <age> is a number of seconds or a number followed by s, m, h, d or w
+
+    -w <sec>                    Wait <sec> seconds for further retained messages
+    --wait=<sec>                Default: {}
+
+    -y                          Don't ask for confirmation before removing hosts
+    --yes                       matching patterns, read from a file or stale hosts
+
     -C                          Check configuration file and exit
     --check
 
@@ -24,7 +48,9 @@ pub fn show_usage() {
     --version
 
 ",
+        env!("CARGO_BIN_NAME"),
         env!("CARGO_BIN_NAME"),
         constants::DEFAULT_CONFIG_FILE,
+        constants::DEFAULT_WAIT,
     );
 }
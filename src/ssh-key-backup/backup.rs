@@ -0,0 +1,160 @@
+use crate::config;
+
+use global::encryption::Identities;
+use global::error::Error;
+use log::{error, info};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::time::Duration;
+
+pub type Database = BTreeMap<String, global::payload::Message>;
+
+pub struct ImportOptions {
+    pub dry_run: bool,
+    pub missing_only: bool,
+    pub wait: Duration,
+}
+
+// Stored key data, indexed by host. A backup must not silently miss hosts, so key data which
+// can't be decrypted or parsed is an error
+pub fn fetch(cfg: &config::Configuration, wait: Duration) -> Result<Database, Error> {
+    let mut result = Database::new();
+    let identities = match &cfg.payload.identity_file {
+        Some(v) => Some(Identities::from_file(v)?),
+        None => None,
+    };
+
+    for (host, payload) in global::transport::new(&cfg.backend).list(wait)?.into_iter() {
+        let msg = match global::encryption::decrypt(
+            &payload,
+            identities.as_ref(),
+            global::constants::DEFAULT_MAX_PAYLOAD_SIZE,
+        )
+        .and_then(|v| global::payload::Message::parse(&v))
+        {
+            Ok(v) => v,
+            Err(e) => {
+                return Err(Error::Payload(format!(
+                    "can't read key data of {}: {}",
+                    host, e
+                )));
+            }
+        };
+        result.insert(host, msg);
+    }
+
+    Ok(result)
+}
+
+pub fn export(cfg: &config::Configuration, file: &str, wait: Duration) -> Result<(), Error> {
+    let data = fetch(cfg, wait)?;
+    let raw = if is_yaml(file) {
+        serde_yaml::to_string(&data)?
+    } else {
+        serde_json::to_string_pretty(&data)?
+    };
+
+    info!("writing key data of {} hosts to {}", data.len(), file);
+    if let Err(e) = fs::write(file, raw) {
+        return Err(Error::Io(format!("can't write {}: {}", file, e)));
+    }
+
+    Ok(())
+}
+
+pub fn read_file(file: &str) -> Result<Database, Error> {
+    let raw = match fs::read_to_string(file) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(Error::Io(format!("can't read {}: {}", file, e)));
+        }
+    };
+
+    let data: Database = if is_yaml(file) {
+        match serde_yaml::from_str(&raw) {
+            Ok(v) => v,
+            Err(e) => {
+                return Err(Error::Payload(format!("can't parse {}: {}", file, e)));
+            }
+        }
+    } else {
+        serde_json::from_str(&raw)?
+    };
+
+    for (host, msg) in data.iter() {
+        if let Err(e) = global::payload::check_version(msg.version.as_deref()) {
+            return Err(Error::Payload(format!("{}: {}", host, e)));
+        }
+    }
+
+    Ok(data)
+}
+
+pub fn import(
+    cfg: &config::Configuration,
+    data: Database,
+    opts: &ImportOptions,
+) -> Result<(), Error> {
+    // Invalid key data would be rejected by all subscribers, so the import fails, including dry runs
+    let mut invalid: Vec<&str> = Vec::new();
+    for (host, msg) in data.iter() {
+        if let Err(e) = config::validate_hostname(host) {
+            return Err(Error::Validation(format!("{}: {}", e, host)));
+        }
+        if let Err(e) = msg.validate() {
+            error!(host = host; "invalid key data for {}: {}", host, e);
+            invalid.push(host);
+        }
+    }
+    if !invalid.is_empty() {
+        return Err(Error::Validation(format!(
+            "invalid key data for {}",
+            invalid.join(", ")
+        )));
+    }
+
+    // Hosts with stored key data, whether it's readable or not (e.g. encrypted)
+    let existing: BTreeSet<String> = if opts.missing_only {
+        global::transport::new(&cfg.backend)
+            .list(opts.wait)?
+            .into_keys()
+            .collect()
+    } else {
+        BTreeSet::new()
+    };
+
+    let mut messages: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+    for (host, mut msg) in data.into_iter() {
+        if existing.contains(&host) {
+            info!(host = host; "key data for {} is already stored, skipping", host);
+            continue;
+        }
+
+        // Host names of keys may have been added or removed in the file
+        msg.set_version();
+        let payload =
+            global::encryption::encrypt(serde_json::to_vec(&msg)?, &cfg.payload.recipients)?;
+        messages.insert(host, payload);
+    }
+
+    if messages.is_empty() {
+        info!("no key data to import");
+        return Ok(());
+    }
+
+    if opts.dry_run {
+        for host in messages.keys() {
+            info!(host = host; "dry run, key data for {} would be imported", host);
+        }
+        return Ok(());
+    }
+
+    global::transport::new(&cfg.backend).publish(&messages)?;
+    info!("imported key data of {} hosts", messages.len());
+
+    Ok(())
+}
+
+fn is_yaml(file: &str) -> bool {
+    file.ends_with(".yaml") || file.ends_with(".yml")
+}
@@ -0,0 +1,60 @@
+use global::error::Error;
+use serde::Deserialize;
+use std::fs;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Configuration {
+    #[serde(default)]
+    pub logging: global::logging::Logging,
+    #[serde(flatten)]
+    pub backend: global::transport::Backend,
+    #[serde(default)]
+    pub payload: Payload,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Payload {
+    pub identity_file: Option<String>,
+    #[serde(default)]
+    pub recipients: Vec<String>,
+}
+
+pub fn parse_config_file(f: &str) -> Result<Configuration, Error> {
+    let raw = match fs::read_to_string(f) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(Error::Config(format!("can't read {}: {}", f, e)));
+        }
+    };
+    let mut parsed: Configuration = serde_yaml::from_str(raw.as_str())?;
+
+    validate(&parsed)?;
+
+    if let Some(mqtt) = parsed.backend.mqtt.as_mut() {
+        mqtt.topic = mqtt.topic.trim_end_matches('/').to_string();
+    }
+
+    Ok(parsed)
+}
+
+fn validate(cfg: &Configuration) -> Result<(), Error> {
+    cfg.backend.validate()?;
+
+    global::encryption::validate_recipients(&cfg.payload.recipients)?;
+    if let Some(v) = &cfg.payload.identity_file {
+        global::encryption::Identities::from_file(v)?;
+    }
+
+    if let Some(mqtt) = &cfg.backend.mqtt {
+        global::mqtt::validate(mqtt, global::mqtt::Topic::Publish)?;
+    }
+
+    Ok(())
+}
+
+pub fn validate_hostname(h: &str) -> Result<(), Error> {
+    if h.is_empty() || h.contains('/') || h.contains('+') || h.contains('#') {
+        return Err(Error::Validation("invalid hostname".to_string()));
+    }
+    Ok(())
+}
@@ -0,0 +1,177 @@
+use crate::backup;
+
+use global::error::Error;
+use log::{debug, warn};
+use std::fs;
+
+// Lines are grouped by the first host name, which is used as the host in the MQTT topic.
+// ssh-key-collector publishes to the topic of gethostname(), so key data imported for another
+// name (e.g. the FQDN instead of the short name) isn't replaced by the collector. The import time
+// is used as publication time, so these hosts are reported as stale if they are never refreshed.
+// Marker lines (@cert-authority, @revoked) and hashed host names can't be represented
+// in the key data and are skipped
+pub fn parse(file: &str) -> Result<backup::Database, Error> {
+    let mut result = backup::Database::new();
+    let now = chrono::Utc::now().timestamp();
+    let raw = match fs::read_to_string(file) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(Error::Io(format!("can't read {}: {}", file, e)));
+        }
+    };
+
+    for (i, line) in raw.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if line.starts_with('@') {
+            warn!("{}:{}: skipping line with marker", file, i + 1);
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 3 {
+            warn!("{}:{}: skipping invalid line", file, i + 1);
+            continue;
+        }
+
+        let hostnames: Vec<String> = fields[0]
+            .split(',')
+            .filter(|h| !h.is_empty())
+            .map(|h| h.to_string())
+            .collect();
+        if hostnames.is_empty() || hostnames.iter().any(|h| h.starts_with('|')) {
+            warn!("{}:{}: skipping line with hashed host names", file, i + 1);
+            continue;
+        }
+
+        let host = topic_host(&hostnames[0]);
+        debug!(host = host; "{}:{}: {} key for {}", file, i + 1, fields[1], host);
+
+        // The host names of the first line are the host names of the message, keys of lines with
+        // other host names keep them as host names of the key
+        let msg = result.entry(host).or_default();
+        if msg.hostname.is_empty() {
+            msg.hostname = hostnames.clone();
+            msg.published = Some(now);
+        }
+
+        let key_type = fields[1];
+        let key_data = fields[2];
+        if let Some(key) = msg
+            .keys
+            .iter_mut()
+            .find(|k| k.key_type == key_type && k.key == key_data)
+        {
+            let mut merged = key.hostname.clone().unwrap_or(msg.hostname.clone());
+            for h in hostnames.into_iter() {
+                if !merged.contains(&h) {
+                    merged.push(h);
+                }
+            }
+            key.hostname = if merged == msg.hostname {
+                None
+            } else {
+                Some(merged)
+            };
+            continue;
+        }
+
+        msg.keys.push(global::payload::Keydata {
+            key_type: key_type.to_string(),
+            key: key_data.to_string(),
+            comment: fields[3..].join(" "),
+            hostname: if hostnames == msg.hostname {
+                None
+            } else {
+                Some(hostnames)
+            },
+        });
+    }
+
+    Ok(result)
+}
+
+// Non-standard ports are written as [host]:port
+fn topic_host(hostname: &str) -> String {
+    match hostname.strip_prefix('[').and_then(|h| h.split_once(']')) {
+        Some((h, _)) => h.to_string(),
+        None => hostname.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mktemp::Temp;
+
+    fn parse_str(content: &str) -> backup::Database {
+        let file = Temp::new_file().unwrap();
+        fs::write(&file, content).unwrap();
+        parse(file.to_str().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn parse_groups_by_first_host_name() {
+        let parsed = parse_str(
+            "# comment\n\nnode01.example.com,node01,10.0.0.1 ssh-ed25519 AAAA root@node01\n",
+        );
+        let msg = &parsed["node01.example.com"];
+        assert_eq!(
+            msg.hostname,
+            vec!["node01.example.com", "node01", "10.0.0.1"]
+        );
+        assert_eq!(msg.keys.len(), 1);
+        assert_eq!(msg.keys[0].key_type, "ssh-ed25519");
+        assert_eq!(msg.keys[0].comment, "root@node01");
+        assert_eq!(msg.keys[0].hostname, None);
+
+        let now = chrono::Utc::now().timestamp();
+        assert!(msg.published.is_some_and(|v| now - v < 60));
+    }
+
+    #[test]
+    fn parse_strips_port_from_topic_host() {
+        let parsed = parse_str("[node02]:2222,[10.0.0.2]:2222 ssh-rsa BBBB\n");
+        assert_eq!(parsed.len(), 1);
+        let msg = &parsed["node02"];
+        assert_eq!(msg.hostname, vec!["[node02]:2222", "[10.0.0.2]:2222"]);
+        assert_eq!(msg.keys[0].comment, "");
+    }
+
+    #[test]
+    fn parse_skips_markers_hashed_and_invalid_lines() {
+        let parsed = parse_str(concat!(
+            "@cert-authority *.example.com ssh-ed25519 CCCC\n",
+            "@revoked node03 ssh-ed25519 DDDD\n",
+            "|1|c2FsdA==|aGFzaA== ssh-ed25519 EEEE\n",
+            "node04,|1|c2FsdA==|aGFzaA== ssh-ed25519 FFFF\n",
+            "node05 ssh-ed25519\n",
+        ));
+        assert!(parsed.is_empty());
+    }
+
+    #[test]
+    fn parse_merges_duplicate_keys() {
+        let parsed = parse_str(concat!(
+            "node06,10.0.0.6 ssh-ed25519 GGGG\n",
+            "node06 ssh-rsa HHHH\n",
+            "node06,10.0.0.6 ssh-ed25519 GGGG\n",
+            "node06,node06-alias ssh-ed25519 GGGG\n",
+        ));
+        let msg = &parsed["node06"];
+        assert_eq!(msg.hostname, vec!["node06", "10.0.0.6"]);
+        assert_eq!(msg.keys.len(), 2);
+        assert_eq!(
+            msg.keys[0].hostname,
+            Some(vec![
+                "node06".to_string(),
+                "10.0.0.6".to_string(),
+                "node06-alias".to_string()
+            ])
+        );
+        assert_eq!(msg.keys[1].hostname, Some(vec!["node06".to_string()]));
+    }
+}
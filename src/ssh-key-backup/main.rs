@@ -0,0 +1,177 @@
+mod backup;
+mod config;
+mod constants;
+mod known_hosts;
+mod usage;
+
+use getopts::Options;
+use log::{debug, error, info};
+use std::time::Duration;
+use std::{env, process};
+
+fn main() {
+    let argv: Vec<String> = env::args().collect();
+    let mut options = Options::new();
+    let mut log_level = log::LevelFilter::Info;
+
+    options.optflag("C", "check", "Check configuration file and exit");
+    options.optflag("D", "debug", "Enable debug output");
+    options.optflag("V", "version", "Show version information");
+    options.optflag("h", "help", "Show help text");
+    options.optopt(
+        "c",
+        "config",
+        "Configuration file",
+        constants::DEFAULT_CONFIG_FILE,
+    );
+    options.optopt("e", "export", "Export key data to file", "<file>");
+    options.optopt("i", "import", "Import key data from file", "<file>");
+    options.optopt(
+        "k",
+        "known-hosts",
+        "Import key data from ssh_known_hosts file",
+        "<file>",
+    );
+    options.optopt(
+        "L",
+        "log-format",
+        "Log format: text, json, syslog or journald",
+        "text",
+    );
+    options.optflag("m", "missing", "Only import hosts without stored key data");
+    options.optflag("n", "dry-run", "Show hosts to import but don't import");
+    options.optflag("q", "quiet", "Quiet operation");
+    options.optopt(
+        "w",
+        "wait",
+        "Seconds to wait for data from the MQTT broker",
+        "sec",
+    );
+
+    let opts = match options.parse(&argv[1..]) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Error: Can't parse command line arguments: {}", e);
+            println!();
+            usage::show_usage();
+            process::exit(global::constants::EXIT_USAGE_ERROR);
+        }
+    };
+
+    if opts.opt_present("h") {
+        usage::show_usage();
+        process::exit(0);
+    }
+
+    if opts.opt_present("V") {
+        usage::show_usage();
+        process::exit(0);
+    }
+
+    if opts.opt_present("D") {
+        log_level = log::LevelFilter::Debug;
+    }
+
+    if opts.opt_present("q") {
+        log_level = log::LevelFilter::Warn;
+    }
+
+    let config_file = match opts.opt_str("c") {
+        Some(v) => v,
+        None => constants::DEFAULT_CONFIG_FILE.to_string(),
+    };
+
+    let log_format = match opts.opt_str("L") {
+        Some(v) => match v.parse::<global::logging::Format>() {
+            Ok(v) => Some(v),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                println!();
+                usage::show_usage();
+                process::exit(global::constants::EXIT_USAGE_ERROR);
+            }
+        },
+        None => None,
+    };
+
+    let wait = match opts.opt_str("w") {
+        Some(v) => match v.parse::<u64>() {
+            Ok(v) if v > 0 => v,
+            _ => {
+                eprintln!("Error: Invalid wait time {}", v);
+                println!();
+                usage::show_usage();
+                process::exit(global::constants::EXIT_USAGE_ERROR);
+            }
+        },
+        None => constants::DEFAULT_WAIT,
+    };
+
+    let export_file = opts.opt_str("e");
+    let import_file = opts.opt_str("i");
+    let known_hosts_file = opts.opt_str("k");
+    let modes = [&export_file, &import_file, &known_hosts_file]
+        .iter()
+        .filter(|m| m.is_some())
+        .count();
+    if modes != 1 && !opts.opt_present("C") {
+        eprintln!("Error: Exactly one of --export, --import or --known-hosts must be used");
+        println!();
+        usage::show_usage();
+        process::exit(global::constants::EXIT_USAGE_ERROR);
+    }
+
+    let configuration = match config::parse_config_file(&config_file) {
+        Ok(v) => v,
+        Err(e) => {
+            global::logging::init_or_exit(
+                log_level,
+                &global::logging::Logging::default(),
+                log_format,
+                global::logging::Output::Stdout,
+            );
+            error!(
+                "parsing of configuration file {} failed: {}",
+                config_file, e
+            );
+            process::exit(e.exit_code());
+        }
+    };
+
+    global::logging::init_or_exit(
+        log_level,
+        &configuration.logging,
+        log_format,
+        global::logging::Output::Stdout,
+    );
+
+    if opts.opt_present("C") {
+        info!("configuration file {} is valid", config_file);
+        process::exit(0);
+    }
+
+    debug!("parsed configuration: {:?}", configuration);
+
+    let import_opts = backup::ImportOptions {
+        dry_run: opts.opt_present("n"),
+        missing_only: opts.opt_present("m"),
+        wait: Duration::from_secs(wait),
+    };
+
+    let result = if let Some(file) = export_file {
+        backup::export(&configuration, &file, Duration::from_secs(wait))
+    } else if let Some(file) = import_file {
+        backup::read_file(&file).and_then(|v| backup::import(&configuration, v, &import_opts))
+    } else if let Some(file) = known_hosts_file {
+        known_hosts::parse(&file).and_then(|v| backup::import(&configuration, v, &import_opts))
+    } else {
+        Ok(())
+    };
+
+    if let Err(e) = result {
+        error!("{}", e);
+        process::exit(e.exit_code());
+    }
+
+    process::exit(0);
+}
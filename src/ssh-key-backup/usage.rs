@@ -0,0 +1,56 @@
+use crate::constants;
+
+pub fn show_usage() {
+    global::usage::show_version();
+    println!(
+        "Usage: {} [-c <cfg>|--config=<cfg>] [-h|--help] [-L <fmt>|--log-format=<fmt>] [-q|--quiet] [-w <sec>|--wait=<sec>] [-C|--check] [-D|--debug] [-V|--version] -e <file>|--export=<file>
+       {} [-c <cfg>|--config=<cfg>] [-h|--help] [-L <fmt>|--log-format=<fmt>] [-q|--quiet] [-w <sec>|--wait=<sec>] [-C|--check] [-D|--debug] [-V|--version] [-m|--missing] [-n|--dry-run] -i <file>|--import=<file>
+       {} [-c <cfg>|--config=<cfg>] [-h|--help] [-L <fmt>|--log-format=<fmt>] [-q|--quiet] [-w <sec>|--wait=<sec>] [-C|--check] [-D|--debug] [-V|--version] [-m|--missing] [-n|--dry-run] -k <file>|--known-hosts=<file>
+
+    -c <cfg>                    Read configuration from file <cfg>
+    --config=<cfg>              Default: {}
+
+    -e <file>                   Export stored key data to <file>.
+    --export=<file>             Files ending with .yaml or .yml are written as YAML, all other files as JSON
+
+    -h                          Shows this text
+    --help
+
+    -i <file>                   Import key data from <file> to the backend.
+    --import=<file>             Files ending with .yaml or .yml are read as YAML, all other files as JSON
+
+    -k <file>                   Import key data from the ssh_known_hosts file <file>
+    --known-hosts=<file>        to the backend
+
+    -L <fmt>                    Log format, one of text, json, syslog or journald
+    --log-format=<fmt>          Overrides the log format from the configuration file
+
+    -m                          Only import hosts without stored key data
+    --missing
+
+    -n                          Show hosts to import, but don't import key data
+    --dry-run
+
+    -q                          Quiet operation.
+    --quiet                     Only warning and critical messages will be logged
+
+    -w <sec>                    Stop collecting data if no data was received
+    --wait=<sec>                for <sec> seconds. Default: {}
+
+    -C                          Check configuration file and exit
+    --check
+
+    -D                          Enable debug log
+    --debug
+
+    -V                          Show version information
+    --version
+
+",
+        env!("CARGO_BIN_NAME"),
+        env!("CARGO_BIN_NAME"),
+        env!("CARGO_BIN_NAME"),
+        constants::DEFAULT_CONFIG_FILE,
+        constants::DEFAULT_WAIT,
+    );
+}
@@ -1,14 +1,20 @@
-use log::debug;
+use crate::constants;
+use crate::discovery;
+use crate::sshd;
+
+use global::error::Error;
 use serde::Deserialize;
-use simple_error::bail;
-use std::error::Error;
 use std::fs;
-use std::path::Path;
-use url::Url;
+use std::net::IpAddr;
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Configuration {
-    pub mqtt: global::mqtt::MQTT,
+    #[serde(default)]
+    pub logging: global::logging::Logging,
+    #[serde(flatten)]
+    pub backend: global::transport::Backend,
+    #[serde(default)]
+    pub payload: Payload,
     #[serde(rename = "ssh-keys")]
     #[serde(default)]
     pub ssh_keys: SSHKeys,
@@ -16,63 +22,198 @@ pub struct Configuration {
     pub ssh_directory: String,
 }
 
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Payload {
+    #[serde(default)]
+    pub compression: global::payload::Compression,
+    #[serde(default)]
+    pub recipients: Vec<String>,
+}
+
 #[derive(Clone, Debug, Default, Deserialize)]
 pub struct SSHKeys {
+    pub discovery: Option<Discovery>,
     #[serde(default)]
-    pub files: Vec<String>,
+    pub files: Vec<global::keys::KeyFile>,
     #[serde(default)]
     pub hostname: Vec<String>,
     pub comment: Option<String>,
+    pub keyscan: Option<Keyscan>,
+    pub sshd_config: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Keyscan {
+    #[serde(default = "default_keyscan_address")]
+    pub address: String,
+    #[serde(default = "default_keyscan_command")]
+    pub command: String,
+    #[serde(default = "default_keyscan_port")]
+    pub port: u16,
+    #[serde(default = "default_keyscan_timeout")]
+    pub timeout: u64,
+}
+
+fn default_keyscan_address() -> String {
+    constants::DEFAULT_KEYSCAN_ADDRESS.to_string()
+}
+
+fn default_keyscan_command() -> String {
+    constants::DEFAULT_KEYSCAN_COMMAND.to_string()
+}
+
+fn default_keyscan_port() -> u16 {
+    constants::DEFAULT_KEYSCAN_PORT
+}
+
+fn default_keyscan_timeout() -> u64 {
+    constants::DEFAULT_KEYSCAN_TIMEOUT
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Discovery {
+    #[serde(default = "default_true")]
+    pub addresses: bool,
+    #[serde(default)]
+    pub exclude_interfaces: Vec<String>,
+    #[serde(default)]
+    pub exclude_networks: Vec<String>,
+    #[serde(default = "default_true")]
+    pub fqdn: bool,
+    #[serde(default)]
+    pub interfaces: Vec<String>,
+    #[serde(default)]
+    pub networks: Vec<String>,
+    #[serde(default = "default_true")]
+    pub short_name: bool,
+}
+
+fn default_true() -> bool {
+    true
 }
 
-pub fn parse_config_file(f: &str) -> Result<Configuration, Box<dyn Error>> {
-    let raw = fs::read_to_string(f)?;
+impl Discovery {
+    // Empty include lists select all interfaces or networks. Patterns and networks were
+    // validated with the configuration
+    pub fn selected(&self, interface: &str, ip: &IpAddr) -> bool {
+        let interface_matches = |patterns: &[String]| {
+            patterns.iter().any(|p| match glob::Pattern::new(p) {
+                Ok(v) => v.matches(interface),
+                Err(_) => false,
+            })
+        };
+        let network_matches = |networks: &[String]| {
+            networks.iter().any(|n| match n.parse::<ipnet::IpNet>() {
+                Ok(v) => v.contains(ip),
+                Err(_) => false,
+            })
+        };
+
+        (self.interfaces.is_empty() || interface_matches(&self.interfaces))
+            && !interface_matches(&self.exclude_interfaces)
+            && (self.networks.is_empty() || network_matches(&self.networks))
+            && !network_matches(&self.exclude_networks)
+    }
+}
+
+pub fn parse_config_file(f: &str) -> Result<Configuration, Error> {
+    let raw = match fs::read_to_string(f) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(Error::Config(format!("can't read {}: {}", f, e)));
+        }
+    };
     let mut parsed: Configuration = serde_yaml::from_str(raw.as_str())?;
 
     validate(&parsed)?;
 
-    parsed.mqtt.topic = parsed.mqtt.topic.trim_end_matches('/').to_string();
-    parsed.mqtt.topic = format!(
-        "{}/{}",
-        parsed.mqtt.topic,
-        gethostname::gethostname().into_string().unwrap()
-    );
+    if let Some(mqtt) = parsed.backend.mqtt.as_mut() {
+        mqtt.topic = mqtt.topic.trim_end_matches('/').to_string();
+    }
 
     Ok(parsed)
 }
 
-fn validate(cfg: &Configuration) -> Result<(), Box<dyn Error>> {
-    if cfg.mqtt.qos > 2 || cfg.mqtt.qos < 0 {
-        bail!("invalid MQTT QoS setting");
+fn validate(cfg: &Configuration) -> Result<(), Error> {
+    cfg.backend.validate()?;
+    global::encryption::validate_recipients(&cfg.payload.recipients)?;
+
+    if let Some(v) = &cfg.ssh_keys.discovery {
+        validate_discovery(v)?;
     }
 
-    if cfg.mqtt.topic.is_empty() || cfg.mqtt.topic.contains('+') || cfg.mqtt.topic.contains('#') {
-        bail!("invalid MQTT topic, wildcards are not allowed in publishing topic")
+    let sources = [
+        !cfg.ssh_keys.files.is_empty(),
+        cfg.ssh_keys.keyscan.is_some(),
+        cfg.ssh_keys.sshd_config.is_some(),
+    ];
+    if sources.iter().filter(|v| **v).count() > 1 {
+        return Err(Error::Config(
+            "only one of files, keyscan or sshd_config can be configured".to_string(),
+        ));
     }
 
-    if cfg.mqtt.timeout == 0 {
-        bail!("invalid MQTT timeout");
+    if let Some(v) = &cfg.ssh_keys.keyscan {
+        if v.address.is_empty() || v.address.starts_with('-') {
+            return Err(Error::Config(format!(
+                "invalid keyscan address {}",
+                v.address
+            )));
+        }
+        if v.port == 0 {
+            return Err(Error::Config("invalid keyscan port".to_string()));
+        }
+        if v.timeout == 0 {
+            return Err(Error::Config("invalid keyscan timeout".to_string()));
+        }
     }
 
-    if cfg.mqtt.reconnect_timeout == 0 {
-        bail!("invalid MQTT reconnect timeout");
+    for f in cfg.ssh_keys.files.iter() {
+        if f.hostname.as_ref().is_some_and(|v| v.is_empty()) {
+            return Err(Error::Config(format!(
+                "empty hostname override for {}",
+                f.file
+            )));
+        }
     }
 
-    if let Err(e) = validate_url(&cfg.mqtt.broker) {
-        bail!("invalid MQTT broker url: {}", e);
+    if let Some(mqtt) = &cfg.backend.mqtt {
+        global::mqtt::validate(mqtt, global::mqtt::Topic::Publish)?;
     }
 
     Ok(())
 }
 
-fn validate_url(s: &str) -> Result<(), Box<dyn Error>> {
-    let _parsed = Url::parse(s)?;
+fn validate_discovery(cfg: &Discovery) -> Result<(), Error> {
+    for p in cfg.interfaces.iter().chain(cfg.exclude_interfaces.iter()) {
+        if let Err(e) = glob::Pattern::new(p) {
+            return Err(Error::Config(format!(
+                "invalid interface pattern {}: {}",
+                p, e
+            )));
+        }
+    }
+
+    for n in cfg.networks.iter().chain(cfg.exclude_networks.iter()) {
+        if let Err(e) = n.parse::<ipnet::IpNet>() {
+            return Err(Error::Config(format!("invalid network {}: {}", n, e)));
+        }
+    }
+
     Ok(())
 }
 
-pub fn fill_missing_fields(cfg: &mut Configuration) -> Result<(), Box<dyn Error>> {
-    if cfg.ssh_keys.files.is_empty() {
-        cfg.ssh_keys.files = find_pub_keys(&cfg.ssh_directory)?;
+pub fn fill_missing_fields(cfg: &mut Configuration) -> Result<(), Error> {
+    if let Some(v) = &cfg.ssh_keys.sshd_config {
+        cfg.ssh_keys.files = sshd::host_key_files(v, &cfg.ssh_directory)?
+            .iter()
+            .map(|f| global::keys::KeyFile::new(f))
+            .collect();
+    } else if cfg.ssh_keys.files.is_empty() && cfg.ssh_keys.keyscan.is_none() {
+        cfg.ssh_keys.files = global::keys::find_pub_keys(&cfg.ssh_directory)?
+            .iter()
+            .map(|f| global::keys::KeyFile::new(f))
+            .collect();
     }
 
     if cfg.ssh_keys.hostname.is_empty() {
@@ -80,38 +221,13 @@ pub fn fill_missing_fields(cfg: &mut Configuration) -> Result<(), Box<dyn Error>
             .hostname
             .push(gethostname::gethostname().into_string().unwrap());
     }
-    Ok(())
-}
-
-fn find_pub_keys(dir: &str) -> Result<Vec<String>, Box<dyn Error>> {
-    let mut result: Vec<String> = Vec::new();
 
-    if dir.is_empty() {
-        bail!("empty directory");
-    }
-
-    debug!("looking for SSH public keys in {}", dir);
-    for entry in (fs::read_dir(Path::new(dir))?).flatten() {
-        let p = entry.path();
-        debug!("processing {:?}", p);
-        if p.is_file() {
-            let fname = match p.to_str() {
-                Some(v) => v,
-                None => {
-                    continue;
-                }
-            };
-
-            let bname = match p.file_name() {
-                Some(v) => v.to_str().unwrap(),
-                None => {
-                    continue;
-                }
-            };
-            if bname.starts_with("ssh_host_") && bname.ends_with("_key.pub") {
-                result.push(fname.to_string());
+    if let Some(v) = &cfg.ssh_keys.discovery {
+        for name in discovery::discover(v)? {
+            if !cfg.ssh_keys.hostname.contains(&name) {
+                cfg.ssh_keys.hostname.push(name);
             }
         }
     }
-    Ok(result)
+    Ok(())
 }
@@ -1 +1,11 @@
 pub const DEFAULT_CONFIG_FILE: &str = "/etc/ssh-key-collector.yaml";
+pub const DEFAULT_KEYSCAN_ADDRESS: &str = "127.0.0.1";
+pub const DEFAULT_KEYSCAN_COMMAND: &str = "ssh-keyscan";
+pub const DEFAULT_KEYSCAN_PORT: u16 = 22;
+pub const DEFAULT_KEYSCAN_TIMEOUT: u64 = 5;
+pub const SSHD_DEFAULT_HOST_KEYS: [&str; 3] = [
+    "ssh_host_rsa_key",
+    "ssh_host_ecdsa_key",
+    "ssh_host_ed25519_key",
+];
+pub const SSHD_MAX_INCLUDE_DEPTH: usize = 16;
@@ -0,0 +1,144 @@
+use crate::config;
+
+use global::error::Error;
+use log::{debug, warn};
+use std::ffi::{CStr, CString};
+use std::net::IpAddr;
+
+// Host names and addresses of the local host, in the order short name, FQDN, addresses
+pub fn discover(cfg: &config::Discovery) -> Result<Vec<String>, Error> {
+    let mut result: Vec<String> = Vec::new();
+    let hostname = gethostname::gethostname().into_string().unwrap();
+
+    if cfg.short_name {
+        if let Some(v) = hostname.split('.').next() {
+            result.push(v.to_string());
+        }
+    }
+
+    if cfg.fqdn {
+        match canonical_name(&hostname) {
+            Ok(Some(v)) if v.contains('.') => result.push(v),
+            Ok(_) => {
+                warn!(
+                    "resolver didn't return a fully qualified name for {}",
+                    hostname
+                );
+            }
+            Err(e) => {
+                warn!("can't resolve fully qualified name of {}: {}", hostname, e);
+            }
+        };
+    }
+
+    if cfg.addresses {
+        result.extend(addresses(cfg)?);
+    }
+
+    debug!("discovered host names and addresses: {:?}", result);
+    Ok(result)
+}
+
+// Same as hostname --fqdn, the canonical name of the host name from the local resolver
+fn canonical_name(hostname: &str) -> Result<Option<String>, Error> {
+    let name = match CString::new(hostname) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(Error::Validation(format!(
+                "invalid host name {}: {}",
+                hostname, e
+            )));
+        }
+    };
+    let hints = libc::addrinfo {
+        ai_flags: libc::AI_CANONNAME,
+        ai_family: libc::AF_UNSPEC,
+        ai_socktype: libc::SOCK_STREAM,
+        ai_protocol: 0,
+        ai_addrlen: 0,
+        ai_addr: std::ptr::null_mut(),
+        ai_canonname: std::ptr::null_mut(),
+        ai_next: std::ptr::null_mut(),
+    };
+    let mut info: *mut libc::addrinfo = std::ptr::null_mut();
+
+    // SAFETY: name and hints outlive the call, info is only read if getaddrinfo succeeded and
+    // is freed exactly once
+    unsafe {
+        let rc = libc::getaddrinfo(name.as_ptr(), std::ptr::null(), &hints, &mut info);
+        if rc != 0 {
+            return Err(Error::Io(
+                CStr::from_ptr(libc::gai_strerror(rc))
+                    .to_string_lossy()
+                    .into_owned(),
+            ));
+        }
+
+        let result = if info.is_null() || (*info).ai_canonname.is_null() {
+            None
+        } else {
+            Some(
+                CStr::from_ptr((*info).ai_canonname)
+                    .to_string_lossy()
+                    .into_owned(),
+            )
+        };
+        libc::freeaddrinfo(info);
+        Ok(result)
+    }
+}
+
+// Loopback, link-local and multicast addresses can't be used to reach the host from other hosts
+fn addresses(cfg: &config::Discovery) -> Result<Vec<String>, Error> {
+    let mut result: Vec<String> = Vec::new();
+
+    let interfaces = match nix::ifaddrs::getifaddrs() {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(Error::Io(format!(
+                "can't get addresses of network interfaces: {}",
+                e
+            )));
+        }
+    };
+
+    for interface in interfaces {
+        let address = match interface.address {
+            Some(v) => v,
+            None => continue,
+        };
+        let ip = if let Some(v) = address.as_sockaddr_in() {
+            IpAddr::V4(v.ip())
+        } else if let Some(v) = address.as_sockaddr_in6() {
+            IpAddr::V6(v.ip())
+        } else {
+            continue;
+        };
+
+        if ip.is_loopback() || ip.is_multicast() || ip.is_unspecified() || is_link_local(&ip) {
+            continue;
+        }
+
+        if !cfg.selected(&interface.interface_name, &ip) {
+            debug!(
+                "skipping address {} of interface {}",
+                ip, interface.interface_name
+            );
+            continue;
+        }
+
+        let address = ip.to_string();
+        if !result.contains(&address) {
+            result.push(address);
+        }
+    }
+
+    Ok(result)
+}
+
+fn is_link_local(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v) => v.is_link_local(),
+        IpAddr::V6(v) => (v.segments()[0] & 0xffc0) == 0xfe80,
+    }
+}
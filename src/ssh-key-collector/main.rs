@@ -1,11 +1,13 @@
-mod collect;
 mod config;
 mod constants;
-mod mqtt;
+mod discovery;
+mod sshd;
 mod usage;
 
 use getopts::Options;
+use global::error::Error;
 use log::{debug, error, info};
+use std::collections::BTreeMap;
 use std::{env, process};
 
 fn main() {
@@ -29,6 +31,12 @@ fn main() {
         "Directory containing SSH keys",
         global::constants::DEFAULT_SSH_DIRECTORY,
     );
+    options.optopt(
+        "L",
+        "log-format",
+        "Log format: text, json, syslog or journald",
+        "text",
+    );
     options.optflag("q", "quiet", "Quiet operation");
 
     let opts = match options.parse(&argv[1..]) {
@@ -37,7 +45,7 @@ fn main() {
             eprintln!("Error: Can't parse command line arguments: {}", e);
             println!();
             usage::show_usage();
-            process::exit(1);
+            process::exit(global::constants::EXIT_USAGE_ERROR);
         }
     };
 
@@ -64,20 +72,43 @@ fn main() {
         None => constants::DEFAULT_CONFIG_FILE.to_string(),
     };
 
-    // XXX: Initialisation of logging should never fail
-    global::logging::init(log_level).unwrap();
+    let log_format = match opts.opt_str("L") {
+        Some(v) => match v.parse::<global::logging::Format>() {
+            Ok(v) => Some(v),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                println!();
+                usage::show_usage();
+                process::exit(global::constants::EXIT_USAGE_ERROR);
+            }
+        },
+        None => None,
+    };
 
     let mut configuration = match config::parse_config_file(&config_file) {
         Ok(v) => v,
         Err(e) => {
+            global::logging::init_or_exit(
+                log_level,
+                &global::logging::Logging::default(),
+                log_format,
+                global::logging::Output::Stdout,
+            );
             error!(
                 "parsing of configuration file {} failed: {}",
                 config_file, e
             );
-            process::exit(1);
+            process::exit(e.exit_code());
         }
     };
 
+    global::logging::init_or_exit(
+        log_level,
+        &configuration.logging,
+        log_format,
+        global::logging::Output::Stdout,
+    );
+
     if opts.opt_present("C") {
         info!("configuration file {} is valid", config_file);
         process::exit(0);
@@ -88,26 +119,78 @@ fn main() {
         None => global::constants::DEFAULT_SSH_DIRECTORY.to_string(),
     };
 
-    config::fill_missing_fields(&mut configuration).unwrap();
-    if configuration.ssh_keys.files.is_empty() {
+    if let Err(e) = config::fill_missing_fields(&mut configuration) {
+        error!("can't complete configuration: {}", e);
+        process::exit(e.exit_code());
+    }
+    if let Some(v) = &configuration.ssh_keys.sshd_config {
+        if configuration.ssh_keys.files.is_empty() {
+            error!(
+                "no public ssh keys found for the host keys configured in {}",
+                v
+            );
+            process::exit(global::constants::EXIT_CONFIG_ERROR);
+        }
+    } else if configuration.ssh_keys.files.is_empty() && configuration.ssh_keys.keyscan.is_none() {
         error!("no public ssh keys found; neither provided by the configuration file nor in the directory {}", configuration.ssh_directory);
-        process::exit(1);
+        process::exit(global::constants::EXIT_CONFIG_ERROR);
     }
 
     debug!("parsed configuration: {:?}", configuration);
-    let keys = match collect::read_key_files(&configuration) {
-        Ok(v) => v,
-        Err(e) => {
-            error!("can't read key files: {}", e);
-            process::exit(1);
-        }
+    let keys = match &configuration.ssh_keys.keyscan {
+        Some(v) => match sshd::keyscan(
+            v,
+            &configuration.ssh_keys.hostname,
+            configuration.ssh_keys.comment.as_deref(),
+        ) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("can't fetch host keys: {}", e);
+                process::exit(e.exit_code());
+            }
+        },
+        None => match global::keys::read_key_files(
+            &configuration.ssh_keys.files,
+            &configuration.ssh_keys.hostname,
+            configuration.ssh_keys.comment.as_deref(),
+        ) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("can't read key files: {}", e);
+                process::exit(e.exit_code());
+            }
+        },
     };
     debug!("parsed key data: {:?}", keys);
 
-    if let Err(e) = mqtt::send(&configuration, &keys) {
+    if let Err(e) = publish(&configuration, &keys) {
         error!("{}", e);
-        process::exit(1);
+        process::exit(e.exit_code());
     };
 
     process::exit(0);
 }
+
+fn publish(cfg: &config::Configuration, keys: &global::payload::Message) -> Result<(), Error> {
+    let payload = match serde_json::to_vec(keys) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(Error::Payload(format!(
+                "can't convert message to JSON: {}",
+                e
+            )));
+        }
+    };
+    // Compressing encrypted data is pointless, so compress first
+    let payload = global::payload::encode(payload, cfg.payload.compression)?;
+    let payload = global::encryption::encrypt(payload, &cfg.payload.recipients)?;
+
+    let host = gethostname::gethostname().into_string().unwrap();
+    let mut transport = global::transport::new(&cfg.backend);
+    info!(
+        "publishing key data of {} to {}",
+        host,
+        transport.location()
+    );
+    transport.publish(&BTreeMap::from([(host, payload)]))
+}
@@ -0,0 +1,406 @@
+use crate::config;
+use crate::constants;
+
+use global::error::Error;
+use global::payload::{Keydata, Message};
+use log::{debug, info, warn};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+// Public keys of the HostKey directives of sshd_config and its included files. Like sshd, the
+// default host keys are used if no HostKey directive is found
+pub fn host_key_files(sshd_config: &str, ssh_directory: &str) -> Result<Vec<String>, Error> {
+    let mut host_keys: Vec<String> = Vec::new();
+    parse_sshd_config(sshd_config, ssh_directory, 0, &mut host_keys)?;
+
+    if host_keys.is_empty() {
+        debug!(
+            "no HostKey directive found in {}, using default host keys",
+            sshd_config
+        );
+        host_keys = constants::SSHD_DEFAULT_HOST_KEYS
+            .iter()
+            .map(|k| format!("{}/{}", ssh_directory, k))
+            .collect();
+    }
+
+    let mut result: Vec<String> = Vec::new();
+    for key in host_keys.into_iter() {
+        // The public key of a host key stored in an agent (HostKeyAgent) is configured directly
+        let public_key = if key.ends_with(".pub") {
+            key
+        } else {
+            format!("{}.pub", key)
+        };
+        if !Path::new(&public_key).is_file() {
+            warn!(
+                "public key {} of host key doesn't exist, skipping",
+                public_key
+            );
+            continue;
+        }
+        if !result.contains(&public_key) {
+            result.push(public_key);
+        }
+    }
+
+    Ok(result)
+}
+
+// HostKey isn't allowed in Match blocks, so the rest of a file after a Match line is skipped
+fn parse_sshd_config(
+    file: &str,
+    ssh_directory: &str,
+    depth: usize,
+    host_keys: &mut Vec<String>,
+) -> Result<(), Error> {
+    if depth > constants::SSHD_MAX_INCLUDE_DEPTH {
+        return Err(Error::Config(format!(
+            "too many nested Include directives in {}",
+            file
+        )));
+    }
+
+    debug!("parsing sshd configuration {}", file);
+    let raw = match fs::read_to_string(file) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(Error::Io(format!("can't read {}: {}", file, e)));
+        }
+    };
+
+    for (i, line) in raw.lines().enumerate() {
+        let (keyword, args) = match split_line(line) {
+            Some(v) => v,
+            None => continue,
+        };
+
+        match keyword.to_lowercase().as_str() {
+            "hostkey" => {
+                for arg in args.into_iter() {
+                    debug!("{}:{}: host key {}", file, i + 1, arg);
+                    host_keys.push(absolute_path(&arg, ssh_directory));
+                }
+            }
+            "include" => {
+                for arg in args.into_iter() {
+                    let pattern = absolute_path(&arg, ssh_directory);
+                    let files = match glob::glob(&pattern) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            return Err(Error::Config(format!(
+                                "{}:{}: invalid Include pattern {}: {}",
+                                file,
+                                i + 1,
+                                arg,
+                                e
+                            )));
+                        }
+                    };
+                    for included in files.flatten() {
+                        if let Some(v) = included.to_str() {
+                            parse_sshd_config(v, ssh_directory, depth + 1, host_keys)?;
+                        }
+                    }
+                }
+            }
+            "match" => break,
+            _ => {}
+        };
+    }
+
+    Ok(())
+}
+
+// Keyword and arguments, keyword and arguments are separated by whitespace or '=',
+// arguments may be quoted
+fn split_line(line: &str) -> Option<(String, Vec<String>)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let (keyword, rest) = match line.find(|c: char| c.is_whitespace() || c == '=') {
+        Some(v) => line.split_at(v),
+        None => (line, ""),
+    };
+    let rest = rest.trim_start();
+    let rest = rest.strip_prefix('=').unwrap_or(rest);
+
+    let mut args: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in rest.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted && current.is_empty() => break,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    args.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        };
+    }
+    if !current.is_empty() {
+        args.push(current);
+    }
+
+    Some((keyword.to_string(), args))
+}
+
+// Like sshd, relative paths are relative to the SSH directory
+fn absolute_path(path: &str, ssh_directory: &str) -> String {
+    if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("{}/{}", ssh_directory, path)
+    }
+}
+
+// Host keys as served by sshd, fetched with ssh-keyscan. The key data doesn't contain comments
+pub fn keyscan(
+    cfg: &config::Keyscan,
+    hostname: &[String],
+    comment: Option<&str>,
+) -> Result<Message, Error> {
+    let mut result = Message::new();
+    result.hostname = hostname.to_vec();
+    result.published = Some(chrono::Utc::now().timestamp());
+
+    info!(
+        "fetching host keys from {} port {} with {}",
+        cfg.address, cfg.port, cfg.command
+    );
+    let output = match Command::new(&cfg.command)
+        .arg("-p")
+        .arg(cfg.port.to_string())
+        .arg("-T")
+        .arg(cfg.timeout.to_string())
+        .arg(&cfg.address)
+        .output()
+    {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(Error::Io(format!("can't run {}: {}", cfg.command, e)));
+        }
+    };
+    for key in parse_keyscan(
+        &String::from_utf8_lossy(&output.stdout),
+        &cfg.command,
+        comment,
+    ) {
+        if let Some(fingerprint) = key.fingerprint() {
+            info!(
+                fingerprint = fingerprint;
+                "found {} key with fingerprint {} on {} port {}",
+                key.key_type, fingerprint, cfg.address, cfg.port
+            );
+        }
+        result.keys.push(key);
+    }
+
+    // ssh-keyscan fails if no host key was received, connection errors are reported on stderr
+    if result.keys.is_empty() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let mut message = format!(
+            "no host keys received from {} port {} ({})",
+            cfg.address, cfg.port, output.status
+        );
+        if !stderr.trim().is_empty() {
+            message.push_str(&format!(": {}", stderr.trim()));
+        }
+        return Err(Error::Io(message));
+    }
+
+    Ok(result)
+}
+
+// Output lines are <address> <type> <key>, keys of the same host key are reported once
+fn parse_keyscan(output: &str, command: &str, comment: Option<&str>) -> Vec<Keydata> {
+    let mut result: Vec<Keydata> = Vec::new();
+    for line in output.lines() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut key: Keydata = match line
+            .split_once(' ')
+            .and_then(|(_, v)| global::keys::parse_public_key(v))
+        {
+            Some(v) => v,
+            None => {
+                warn!("skipping invalid output of {}: {}", command, line);
+                continue;
+            }
+        };
+        if let Some(v) = comment {
+            key.comment = v.to_string();
+        }
+        if !result.contains(&key) {
+            result.push(key);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mktemp::Temp;
+
+    fn args(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn write(dir: &Temp, file: &str, content: &str) -> String {
+        let path = dir.as_path().join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    fn parse(file: &str, ssh_directory: &str) -> Result<Vec<String>, Error> {
+        let mut host_keys: Vec<String> = Vec::new();
+        parse_sshd_config(file, ssh_directory, 0, &mut host_keys)?;
+        Ok(host_keys)
+    }
+
+    #[test]
+    fn split_line_separates_keyword_and_arguments() {
+        assert_eq!(split_line("  # comment"), None);
+        assert_eq!(split_line(""), None);
+        assert_eq!(
+            split_line("HostKey /etc/ssh/key"),
+            Some(("HostKey".to_string(), args(&["/etc/ssh/key"])))
+        );
+        assert_eq!(
+            split_line("HostKey=/etc/ssh/key"),
+            Some(("HostKey".to_string(), args(&["/etc/ssh/key"])))
+        );
+        assert_eq!(
+            split_line("HostKey = /etc/ssh/key"),
+            Some(("HostKey".to_string(), args(&["/etc/ssh/key"])))
+        );
+        assert_eq!(
+            split_line("Include \"/etc/ssh/with space.conf\" b.conf  # trailing"),
+            Some((
+                "Include".to_string(),
+                args(&["/etc/ssh/with space.conf", "b.conf"])
+            ))
+        );
+        assert_eq!(
+            split_line("Banner \"#not a comment\""),
+            Some(("Banner".to_string(), args(&["#not a comment"])))
+        );
+        assert_eq!(split_line("Match"), Some(("Match".to_string(), Vec::new())));
+    }
+
+    #[test]
+    fn parse_sshd_config_follows_includes() {
+        let dir = Temp::new_dir().unwrap();
+        let ssh_directory = dir.to_str().unwrap();
+        let config = write(
+            &dir,
+            "sshd_config",
+            "Include sshd_config.d/*.conf\nhostkey ssh_host_ed25519_key\n",
+        );
+        write(
+            &dir,
+            "sshd_config.d/10-rsa.conf",
+            "HostKey /etc/ssh/ssh_host_rsa_key\n",
+        );
+        write(
+            &dir,
+            "sshd_config.d/20-ecdsa.conf",
+            "HostKey=\"/etc/ssh/ssh_host_ecdsa_key\"\n",
+        );
+        write(&dir, "sshd_config.d/30-other.txt", "HostKey /ignored\n");
+
+        assert_eq!(
+            parse(&config, ssh_directory).unwrap(),
+            vec![
+                "/etc/ssh/ssh_host_rsa_key".to_string(),
+                "/etc/ssh/ssh_host_ecdsa_key".to_string(),
+                format!("{}/ssh_host_ed25519_key", ssh_directory),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_sshd_config_stops_at_match() {
+        let dir = Temp::new_dir().unwrap();
+        let config = write(
+            &dir,
+            "sshd_config",
+            "HostKey /etc/ssh/a\nMatch User backup\n  HostKey /etc/ssh/b\n",
+        );
+        assert_eq!(
+            parse(&config, dir.to_str().unwrap()).unwrap(),
+            vec!["/etc/ssh/a".to_string()]
+        );
+    }
+
+    #[test]
+    fn parse_sshd_config_limits_include_depth() {
+        let dir = Temp::new_dir().unwrap();
+        let config = write(&dir, "sshd_config", "Include sshd_config\n");
+        assert!(matches!(
+            parse(&config, dir.to_str().unwrap()),
+            Err(Error::Config(_))
+        ));
+        assert!(matches!(
+            parse(&format!("{}/missing", dir.to_str().unwrap()), "/etc/ssh"),
+            Err(Error::Io(_))
+        ));
+    }
+
+    #[test]
+    fn host_key_files_uses_existing_public_keys() {
+        let dir = Temp::new_dir().unwrap();
+        let ssh_directory = dir.to_str().unwrap();
+        let config = write(
+            &dir,
+            "sshd_config",
+            "HostKey a_key\nHostKey b_key.pub\nHostKey a_key\nHostKey missing_key\n",
+        );
+        write(&dir, "a_key.pub", "");
+        write(&dir, "b_key.pub", "");
+        assert_eq!(
+            host_key_files(&config, ssh_directory).unwrap(),
+            vec![
+                format!("{}/a_key.pub", ssh_directory),
+                format!("{}/b_key.pub", ssh_directory),
+            ]
+        );
+
+        // Without HostKey directives, the default host keys are used
+        let config = write(&dir, "sshd_config", "Port 22\n");
+        write(&dir, "ssh_host_ed25519_key.pub", "");
+        assert_eq!(
+            host_key_files(&config, ssh_directory).unwrap(),
+            vec![format!("{}/ssh_host_ed25519_key.pub", ssh_directory)]
+        );
+    }
+
+    #[test]
+    fn parse_keyscan_reads_keys() {
+        let output = concat!(
+            "# 127.0.0.1:22 SSH-2.0-OpenSSH_9.6\n",
+            "127.0.0.1 ssh-ed25519 AAAA\n",
+            "127.0.0.1 ssh-rsa BBBB\n",
+            "127.0.0.1 ssh-ed25519 AAAA\n",
+            "garbage\n",
+            "\n",
+        );
+        let keys = parse_keyscan(output, "ssh-keyscan", None);
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].key_type, "ssh-ed25519");
+        assert_eq!(keys[0].key, "AAAA");
+        assert_eq!(keys[0].comment, "");
+        assert_eq!(keys[1].key_type, "ssh-rsa");
+
+        let keys = parse_keyscan(output, "ssh-keyscan", Some("root@node01"));
+        assert!(keys.iter().all(|k| k.comment == "root@node01"));
+    }
+}
@@ -3,7 +3,7 @@ use crate::constants;
 pub fn show_usage() {
     global::usage::show_version();
     println!(
-        "Usage: {} [-c <cfg>|--config=<cfg>] [-d <ssh_dir>|--ssh-directory=<ssh_dir>] [-h|--help] [-q|--quiet] [-C|--check] [-D|--debug] [-V|--version]
+        "Usage: {} [-c <cfg>|--config=<cfg>] [-d <ssh_dir>|--ssh-directory=<ssh_dir>] [-h|--help] [-L <fmt>|--log-format=<fmt>] [-q|--quiet] [-C|--check] [-D|--debug] [-V|--version]
 
     -c <cfg>                    Read configuration from file <cfg>
     --config=<cfg>              Default: {}
@@ -14,6 +14,9 @@ pub fn show_usage() {
     -h                          Shows this text
     --help
 
+    -L <fmt>                    Log format, one of text, json, syslog or journald
+    --log-format=<fmt>          Overrides the log format from the configuration file
+
     -q                          Quiet operation.
     --quiet                     Only warning and critical messages will be logged
 
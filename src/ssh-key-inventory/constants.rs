@@ -0,0 +1,2 @@
+pub const DEFAULT_CONFIG_FILE: &str = "/etc/ssh-key-inventory.yaml";
+pub const DEFAULT_WAIT: u64 = 5;
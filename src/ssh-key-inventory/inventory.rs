@@ -0,0 +1,84 @@
+use crate::config;
+
+use global::encryption::Identities;
+use global::error::Error;
+use log::{debug, warn};
+use serde::Serialize;
+use std::time::Duration;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Entry {
+    pub host: String,
+    pub hostname: Vec<String>,
+    #[serde(rename = "type")]
+    pub key_type: String,
+    pub fingerprint: String,
+    pub comment: String,
+}
+
+pub struct Filter {
+    pub host: Option<glob::Pattern>,
+    pub key_type: Option<String>,
+}
+
+pub fn collect(
+    cfg: &config::Configuration,
+    wait: Duration,
+    filter: &Filter,
+) -> Result<Vec<Entry>, Error> {
+    let mut result: Vec<Entry> = Vec::new();
+    let identities = match &cfg.payload.identity_file {
+        Some(v) => Some(Identities::from_file(v)?),
+        None => None,
+    };
+    let stored = global::transport::new(&cfg.backend).list(wait)?;
+
+    for (host, payload) in stored.into_iter() {
+        // Skipping encrypted key data would silently hide hosts from the inventory
+        let payload = match global::encryption::decrypt(
+            &payload,
+            identities.as_ref(),
+            global::constants::DEFAULT_MAX_PAYLOAD_SIZE,
+        ) {
+            Ok(v) => v,
+            Err(e) => {
+                return Err(Error::Payload(format!(
+                    "can't read key data of {}: {}",
+                    host, e
+                )));
+            }
+        };
+        let msg = match global::payload::Message::parse(&payload) {
+            Ok(v) => v,
+            Err(e) => {
+                warn!(host = host; "can't parse key data of {}: {}", host, e);
+                continue;
+            }
+        };
+
+        if let Some(pattern) = &filter.host {
+            if !pattern.matches(&host) && !msg.all_hostnames().any(|h| pattern.matches(h)) {
+                debug!("{} doesn't match host filter, skipping", host);
+                continue;
+            }
+        }
+
+        for key in msg.keys.iter() {
+            if let Some(key_type) = &filter.key_type {
+                if key.key_type != *key_type {
+                    continue;
+                }
+            }
+
+            result.push(Entry {
+                host: host.clone(),
+                hostname: msg.hostnames(key).to_vec(),
+                key_type: key.key_type.clone(),
+                fingerprint: key.fingerprint().unwrap_or_default(),
+                comment: key.comment.clone(),
+            });
+        }
+    }
+
+    Ok(result)
+}
@@ -0,0 +1,175 @@
+mod config;
+mod constants;
+mod inventory;
+mod output;
+mod usage;
+
+use getopts::Options;
+use log::{debug, error, info};
+use std::time::Duration;
+use std::{env, process};
+
+fn main() {
+    let argv: Vec<String> = env::args().collect();
+    let mut options = Options::new();
+    // Only warnings and errors are logged by default to keep the output usable
+    let mut log_level = log::LevelFilter::Warn;
+
+    options.optflag("C", "check", "Check configuration file and exit");
+    options.optflag("D", "debug", "Enable debug output");
+    options.optopt("H", "host", "Filter by host name glob pattern", "pattern");
+    options.optflag("V", "version", "Show version information");
+    options.optflag("h", "help", "Show help text");
+    options.optopt(
+        "c",
+        "config",
+        "Configuration file",
+        constants::DEFAULT_CONFIG_FILE,
+    );
+    options.optopt("f", "format", "Output format: table, json or csv", "table");
+    options.optopt(
+        "L",
+        "log-format",
+        "Log format: text, json, syslog or journald",
+        "text",
+    );
+    options.optopt("t", "type", "Filter by key type", "type");
+    options.optopt(
+        "w",
+        "wait",
+        "Seconds to wait for data from the MQTT broker",
+        "sec",
+    );
+
+    let opts = match options.parse(&argv[1..]) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Error: Can't parse command line arguments: {}", e);
+            println!();
+            usage::show_usage();
+            process::exit(global::constants::EXIT_USAGE_ERROR);
+        }
+    };
+
+    if opts.opt_present("h") {
+        usage::show_usage();
+        process::exit(0);
+    }
+
+    if opts.opt_present("V") {
+        usage::show_usage();
+        process::exit(0);
+    }
+
+    if opts.opt_present("D") {
+        log_level = log::LevelFilter::Debug;
+    }
+
+    let config_file = match opts.opt_str("c") {
+        Some(v) => v,
+        None => constants::DEFAULT_CONFIG_FILE.to_string(),
+    };
+
+    let log_format = match opts.opt_str("L") {
+        Some(v) => match v.parse::<global::logging::Format>() {
+            Ok(v) => Some(v),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                println!();
+                usage::show_usage();
+                process::exit(global::constants::EXIT_USAGE_ERROR);
+            }
+        },
+        None => None,
+    };
+
+    let format = match opts.opt_str("f") {
+        Some(v) => match v.parse::<output::Format>() {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                println!();
+                usage::show_usage();
+                process::exit(global::constants::EXIT_USAGE_ERROR);
+            }
+        },
+        None => output::Format::Table,
+    };
+
+    let wait = match opts.opt_str("w") {
+        Some(v) => match v.parse::<u64>() {
+            Ok(v) if v > 0 => v,
+            _ => {
+                eprintln!("Error: Invalid wait time {}", v);
+                println!();
+                usage::show_usage();
+                process::exit(global::constants::EXIT_USAGE_ERROR);
+            }
+        },
+        None => constants::DEFAULT_WAIT,
+    };
+
+    let host_pattern = match opts.opt_str("H") {
+        Some(v) => match glob::Pattern::new(&v) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                eprintln!("Error: Invalid host pattern {}: {}", v, e);
+                println!();
+                usage::show_usage();
+                process::exit(global::constants::EXIT_USAGE_ERROR);
+            }
+        },
+        None => None,
+    };
+
+    let filter = inventory::Filter {
+        host: host_pattern,
+        key_type: opts.opt_str("t"),
+    };
+
+    let configuration = match config::parse_config_file(&config_file) {
+        Ok(v) => v,
+        Err(e) => {
+            global::logging::init_or_exit(
+                log_level,
+                &global::logging::Logging::default(),
+                log_format,
+                global::logging::Output::Stderr,
+            );
+            error!(
+                "parsing of configuration file {} failed: {}",
+                config_file, e
+            );
+            process::exit(e.exit_code());
+        }
+    };
+
+    global::logging::init_or_exit(
+        log_level,
+        &configuration.logging,
+        log_format,
+        global::logging::Output::Stderr,
+    );
+
+    if opts.opt_present("C") {
+        info!("configuration file {} is valid", config_file);
+        process::exit(0);
+    }
+
+    debug!("parsed configuration: {:?}", configuration);
+
+    let entries = match inventory::collect(&configuration, Duration::from_secs(wait), &filter) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("can't fetch key data: {}", e);
+            process::exit(e.exit_code());
+        }
+    };
+
+    if let Err(e) = output::print(&entries, &format) {
+        error!("can't print key data: {}", e);
+        process::exit(e.exit_code());
+    }
+
+    process::exit(0);
+}
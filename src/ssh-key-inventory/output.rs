@@ -0,0 +1,80 @@
+use crate::inventory;
+
+use global::error::Error;
+use std::str::FromStr;
+
+pub enum Format {
+    Csv,
+    Json,
+    Table,
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            "table" => Ok(Format::Table),
+            _ => Err(Error::Validation(format!("invalid output format {}", s))),
+        }
+    }
+}
+
+const HEADER: [&str; 5] = ["HOST", "HOSTNAMES", "TYPE", "FINGERPRINT", "COMMENT"];
+
+pub fn print(entries: &[inventory::Entry], format: &Format) -> Result<(), Error> {
+    match format {
+        Format::Csv => print_csv(entries),
+        Format::Json => println!("{}", serde_json::to_string_pretty(entries)?),
+        Format::Table => print_table(entries),
+    };
+    Ok(())
+}
+
+fn columns(entry: &inventory::Entry) -> [String; 5] {
+    [
+        entry.host.clone(),
+        entry.hostname.join(","),
+        entry.key_type.clone(),
+        entry.fingerprint.clone(),
+        entry.comment.clone(),
+    ]
+}
+
+fn print_table(entries: &[inventory::Entry]) {
+    let rows: Vec<[String; 5]> = entries.iter().map(columns).collect();
+    let mut width: Vec<usize> = HEADER.iter().map(|h| h.len()).collect();
+    for row in rows.iter() {
+        for (i, col) in row.iter().enumerate() {
+            width[i] = width[i].max(col.len());
+        }
+    }
+
+    let header: Vec<String> = HEADER.iter().map(|h| h.to_string()).collect();
+    for row in std::iter::once(header).chain(rows.into_iter().map(|r| r.to_vec())) {
+        let line: Vec<String> = row
+            .iter()
+            .enumerate()
+            .map(|(i, col)| format!("{:<w$}", col, w = width[i]))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+fn print_csv(entries: &[inventory::Entry]) {
+    println!("{}", HEADER.map(|h| h.to_lowercase()).join(","));
+    for entry in entries.iter() {
+        let line: Vec<String> = columns(entry).iter().map(|c| csv_quote(c)).collect();
+        println!("{}", line.join(","));
+    }
+}
+
+fn csv_quote(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
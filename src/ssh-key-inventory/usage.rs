@@ -0,0 +1,43 @@
+use crate::constants;
+
+pub fn show_usage() {
+    global::usage::show_version();
+    println!(
+        "Usage: {} [-c <cfg>|--config=<cfg>] [-f <fmt>|--format=<fmt>] [-h|--help] [-t <type>|--type=<type>] [-w <sec>|--wait=<sec>] [-C|--check] [-D|--debug] [-H <pattern>|--host=<pattern>] [-L <fmt>|--log-format=<fmt>] [-V|--version]
+
+    -c <cfg>                    Read configuration from file <cfg>
+    --config=<cfg>              Default: {}
+
+    -f <fmt>                    Output format, one of table, json or csv
+    --format=<fmt>              Default: table
+
+    -h                          Shows this text
+    --help
+
+    -t <type>                   Only show keys of type <type>, e.g. ssh-ed25519
+    --type=<type>
+
+    -w <sec>                    Stop collecting data if no data was received
+    --wait=<sec>                for <sec> seconds. Default: {}
+
+    -C                          Check configuration file and exit
+    --check
+
+    -D                          Enable debug log
+    --debug
+
+    -H <pattern>                Only show hosts with host name or alias
+    --host=<pattern>            matching the glob <pattern>
+
+    -L <fmt>                    Log format, one of text, json, syslog or journald
+    --log-format=<fmt>          Overrides the log format from the configuration file
+
+    -V                          Show version information
+    --version
+
+",
+        env!("CARGO_BIN_NAME"),
+        constants::DEFAULT_CONFIG_FILE,
+        constants::DEFAULT_WAIT,
+    );
+}
@@ -0,0 +1,104 @@
+use crate::constants;
+
+use global::error::Error;
+use serde::Deserialize;
+use std::fs;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Configuration {
+    #[serde(default)]
+    pub logging: global::logging::Logging,
+    #[serde(flatten)]
+    pub backend: global::transport::Backend,
+    #[serde(default)]
+    pub payload: Payload,
+    #[serde(default)]
+    pub server: Server,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct Payload {
+    pub identity_file: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Server {
+    #[serde(default = "default_server_listen")]
+    pub listen: String,
+    #[serde(default)]
+    pub password: String,
+    #[serde(default = "default_server_threads")]
+    pub threads: usize,
+    #[serde(default)]
+    pub user: String,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Server {
+            listen: default_server_listen(),
+            password: String::new(),
+            threads: default_server_threads(),
+            user: String::new(),
+        }
+    }
+}
+
+fn default_server_listen() -> String {
+    constants::DEFAULT_LISTEN.to_string()
+}
+
+fn default_server_threads() -> usize {
+    constants::DEFAULT_THREADS
+}
+
+pub fn parse_config_file(f: &str) -> Result<Configuration, Error> {
+    let raw = match fs::read_to_string(f) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(Error::Config(format!("can't read {}: {}", f, e)));
+        }
+    };
+    let mut parsed: Configuration = serde_yaml::from_str(raw.as_str())?;
+
+    validate(&parsed)?;
+
+    if let Some(mqtt) = parsed.backend.mqtt.as_mut() {
+        mqtt.topic = mqtt.topic.trim_end_matches('/').to_string();
+    }
+
+    Ok(parsed)
+}
+
+fn validate(cfg: &Configuration) -> Result<(), Error> {
+    cfg.backend.validate()?;
+
+    if let Some(mqtt) = &cfg.backend.mqtt {
+        global::mqtt::validate(mqtt, global::mqtt::Topic::Subscribe)?;
+    }
+
+    if let Some(v) = &cfg.payload.identity_file {
+        global::encryption::Identities::from_file(v)?;
+    }
+
+    if cfg.server.listen.parse::<std::net::SocketAddr>().is_err() {
+        return Err(Error::Config(format!(
+            "invalid listen address {} for server",
+            cfg.server.listen
+        )));
+    }
+
+    if cfg.server.threads == 0 {
+        return Err(Error::Config(
+            "invalid number of threads for server".to_string(),
+        ));
+    }
+
+    if cfg.server.user.is_empty() != cfg.server.password.is_empty() {
+        return Err(Error::Config(
+            "user and password for server must be set both".to_string(),
+        ));
+    }
+
+    Ok(())
+}
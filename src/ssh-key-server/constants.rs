@@ -0,0 +1,6 @@
+pub const DEFAULT_CONFIG_FILE: &str = "/etc/ssh-key-server.yaml";
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:8080";
+pub const DEFAULT_THREADS: usize = 4;
+pub const KEYS_PATH: &str = "/keys";
+pub const KNOWN_HOSTS_PATH: &str = "/known_hosts";
+pub const REALM: &str = "ssh-key-server";
@@ -0,0 +1,99 @@
+use global::encryption::Identities;
+use global::error::Error;
+use global::state::{Change, KeyMap};
+use log::{debug, error, info};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+pub type Shared = Arc<RwLock<Database>>;
+
+// The ETag changes with every change of the key data. The start time of the process is part of
+// the ETag, so clients don't mistake the data of a restarted server for their cached data.
+// Until the initial transfer of key data is finished, the key data is incomplete and not served
+pub struct Database {
+    pub keys: KeyMap,
+    generation: u64,
+    started: i64,
+    ready: bool,
+}
+
+impl Database {
+    pub fn new() -> Self {
+        Database {
+            keys: KeyMap::new(),
+            generation: 0,
+            started: chrono::Utc::now().timestamp(),
+            ready: false,
+        }
+    }
+
+    pub fn shared() -> Shared {
+        Arc::new(RwLock::new(Database::new()))
+    }
+
+    pub fn etag(&self) -> String {
+        format!("\"{:x}-{:x}\"", self.started, self.generation)
+    }
+
+    pub fn update(&mut self, host: &str, payload: &[u8], identities: Option<&Identities>) {
+        let result = match global::encryption::decrypt(
+            payload,
+            identities,
+            global::constants::DEFAULT_MAX_PAYLOAD_SIZE,
+        ) {
+            Ok(v) => self.keys.update(host, &v),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(Change::Added) => {
+                info!(host = host; "adding key data of {}", host);
+                self.generation += 1;
+            }
+            Ok(Change::Changed) => {
+                info!(host = host; "key data of {} changed", host);
+                self.generation += 1;
+            }
+            Ok(Change::Removed) => {
+                info!(host = host; "removing key data of {}", host);
+                self.generation += 1;
+            }
+            Ok(Change::Unchanged) => {
+                debug!(host = host; "key data of {} has not changed", host);
+            }
+            Err(Error::Validation(e)) => {
+                error!(host = host; "rejecting key data for {}: {}", host, e);
+            }
+            Err(e) => {
+                error!(host = host; "can't parse key data of {}: {}", host, e);
+            }
+        };
+    }
+
+    pub fn clear(&mut self) {
+        self.keys.clear();
+        self.generation += 1;
+        self.ready = false;
+    }
+
+    pub fn ready(&self) -> bool {
+        self.ready
+    }
+
+    pub fn set_ready(&mut self) {
+        self.ready = true;
+        self.generation += 1;
+    }
+}
+
+pub fn read(database: &Shared) -> Result<RwLockReadGuard<'_, Database>, Error> {
+    match database.read() {
+        Ok(v) => Ok(v),
+        Err(e) => Err(Error::Io(format!("can't lock key data: {}", e))),
+    }
+}
+
+pub fn write(database: &Shared) -> Result<RwLockWriteGuard<'_, Database>, Error> {
+    match database.write() {
+        Ok(v) => Ok(v),
+        Err(e) => Err(Error::Io(format!("can't lock key data: {}", e))),
+    }
+}
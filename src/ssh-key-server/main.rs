@@ -0,0 +1,139 @@
+mod config;
+mod constants;
+mod database;
+mod server;
+mod subscribe;
+mod usage;
+
+use getopts::Options;
+use log::{debug, error, info};
+use std::thread;
+use std::{env, process};
+
+fn main() {
+    let argv: Vec<String> = env::args().collect();
+    let mut options = Options::new();
+    let mut log_level = log::LevelFilter::Info;
+
+    options.optflag("C", "check", "Check configuration file and exit");
+    options.optflag("D", "debug", "Enable debug output");
+    options.optflag("V", "version", "Show version information");
+    options.optflag("h", "help", "Show help text");
+    options.optopt(
+        "c",
+        "config",
+        "Configuration file",
+        constants::DEFAULT_CONFIG_FILE,
+    );
+    options.optopt(
+        "L",
+        "log-format",
+        "Log format: text, json, syslog or journald",
+        "text",
+    );
+    options.optflag("q", "quiet", "Quiet operation");
+
+    let opts = match options.parse(&argv[1..]) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Error: Can't parse command line arguments: {}", e);
+            println!();
+            usage::show_usage();
+            process::exit(global::constants::EXIT_USAGE_ERROR);
+        }
+    };
+
+    if opts.opt_present("h") {
+        usage::show_usage();
+        process::exit(0);
+    }
+
+    if opts.opt_present("V") {
+        usage::show_usage();
+        process::exit(0);
+    }
+
+    if opts.opt_present("D") {
+        log_level = log::LevelFilter::Debug;
+    }
+
+    if opts.opt_present("q") {
+        log_level = log::LevelFilter::Warn;
+    }
+
+    let config_file = match opts.opt_str("c") {
+        Some(v) => v,
+        None => constants::DEFAULT_CONFIG_FILE.to_string(),
+    };
+
+    let log_format = match opts.opt_str("L") {
+        Some(v) => match v.parse::<global::logging::Format>() {
+            Ok(v) => Some(v),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                println!();
+                usage::show_usage();
+                process::exit(global::constants::EXIT_USAGE_ERROR);
+            }
+        },
+        None => None,
+    };
+
+    let configuration = match config::parse_config_file(&config_file) {
+        Ok(v) => v,
+        Err(e) => {
+            global::logging::init_or_exit(
+                log_level,
+                &global::logging::Logging::default(),
+                log_format,
+                global::logging::Output::Stdout,
+            );
+            error!(
+                "parsing of configuration file {} failed: {}",
+                config_file, e
+            );
+            process::exit(e.exit_code());
+        }
+    };
+
+    global::logging::init_or_exit(
+        log_level,
+        &configuration.logging,
+        log_format,
+        global::logging::Output::Stdout,
+    );
+
+    if opts.opt_present("C") {
+        info!("configuration file {} is valid", config_file);
+        process::exit(0);
+    }
+
+    debug!("parsed configuration: {:?}", configuration);
+
+    let signals = match global::signals::Signals::register() {
+        Ok(v) => v,
+        Err(e) => {
+            error!("can't register signal handlers: {}", e);
+            process::exit(e.exit_code());
+        }
+    };
+
+    let database = database::Database::shared();
+    let server_cfg = configuration.server.clone();
+    let server_database = database.clone();
+
+    thread::spawn(move || {
+        if let Err(e) = server::serve(&server_cfg, server_database) {
+            error!("HTTP server failed: {}", e);
+            process::exit(e.exit_code());
+        }
+    });
+
+    if let Err(e) = subscribe::run(configuration, &config_file, &database, &signals) {
+        error!("subscription to key data failed: {}", e);
+        process::exit(e.exit_code());
+    }
+
+    info!("shutdown complete");
+    process::exit(0);
+}
@@ -0,0 +1,201 @@
+use crate::config;
+use crate::constants;
+use crate::database::{self, Shared};
+
+use base64::Engine;
+use global::error::Error;
+use global::matcher::Matcher;
+use global::payload::Message;
+use global::state::KeyMap;
+use log::{debug, error, info};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::thread;
+use url::Url;
+
+type Response = tiny_http::Response<std::io::Cursor<Vec<u8>>>;
+
+pub fn serve(cfg: &config::Server, database: Shared) -> Result<(), Error> {
+    let server = match tiny_http::Server::http(&cfg.listen) {
+        Ok(v) => Arc::new(v),
+        Err(e) => {
+            return Err(Error::Io(format!(
+                "can't start HTTP listener on {}: {}",
+                cfg.listen, e
+            )));
+        }
+    };
+    info!(
+        "serving key data on http://{} with {} threads",
+        cfg.listen, cfg.threads
+    );
+
+    let mut workers = Vec::new();
+    for _ in 0..cfg.threads {
+        let server = server.clone();
+        let database = database.clone();
+        let cfg = cfg.clone();
+        workers.push(thread::spawn(move || {
+            for request in server.incoming_requests() {
+                handle(&cfg, &database, request);
+            }
+        }));
+    }
+
+    for worker in workers {
+        let _ = worker.join();
+    }
+    Ok(())
+}
+
+fn handle(cfg: &config::Server, database: &Shared, request: tiny_http::Request) {
+    debug!("request {} {}", request.method(), request.url());
+
+    let response = if *request.method() != tiny_http::Method::Get
+        && *request.method() != tiny_http::Method::Head
+    {
+        text("method not allowed", 405)
+    } else if !authorized(cfg, &request) {
+        text("unauthorized", 401).with_header(header(
+            "WWW-Authenticate",
+            &format!("Basic realm=\"{}\"", constants::REALM),
+        ))
+    } else {
+        match respond(database, &request) {
+            Ok(v) => v,
+            Err(Error::Validation(e)) => text(&e, 400),
+            Err(e) => {
+                error!("can't process request for {}: {}", request.url(), e);
+                text("internal server error", 500)
+            }
+        }
+    };
+
+    if let Err(e) = request.respond(response) {
+        error!("can't send response: {}", e);
+    }
+}
+
+fn authorized(cfg: &config::Server, request: &tiny_http::Request) -> bool {
+    if cfg.user.is_empty() {
+        return true;
+    }
+
+    let expected = format!(
+        "Basic {}",
+        base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", cfg.user, cfg.password))
+    );
+    request
+        .headers()
+        .iter()
+        .any(|h| h.field.equiv("Authorization") && h.value.as_str() == expected)
+}
+
+// Supported requests:
+//   /keys                 key data of all hosts as JSON object, indexed by host
+//   /keys/<host>          key data of <host> as JSON
+//   /known_hosts          ssh_known_hosts file of all hosts
+// /keys and /known_hosts accept the query parameters pattern=<glob> and regex=<regex> to select
+// hosts by host name or one of their host names in ssh_known_hosts
+fn respond(database: &Shared, request: &tiny_http::Request) -> Result<Response, Error> {
+    let url = Url::parse(&format!("http://localhost{}", request.url()))?;
+    let mut matchers: Vec<Matcher> = Vec::new();
+    for (key, value) in url.query_pairs() {
+        match key.as_ref() {
+            "pattern" => matchers.push(Matcher::new(&value, false)?),
+            "regex" => matchers.push(Matcher::new(&value, true)?),
+            _ => {
+                return Err(Error::Validation(format!(
+                    "unknown query parameter {}",
+                    key
+                )));
+            }
+        };
+    }
+
+    let path = url.path();
+    let database = database::read(database)?;
+    if !database.ready()
+        && (path == constants::KNOWN_HOSTS_PATH
+            || path
+                .strip_prefix(constants::KEYS_PATH)
+                .is_some_and(|v| v.is_empty() || v.starts_with('/')))
+    {
+        // Clients must not mistake incomplete key data for removed hosts
+        return Ok(
+            text("initial transfer of key data in progress", 503).with_header(header(
+                "Retry-After",
+                &global::constants::INITIAL_SYNC_QUIET_PERIOD.to_string(),
+            )),
+        );
+    }
+    let etag = database.etag();
+    let cached = request
+        .headers()
+        .iter()
+        .any(|h| h.field.equiv("If-None-Match") && h.value.as_str() == etag);
+
+    let selected = |host: &str, msg: &Message| -> bool {
+        matchers.is_empty()
+            || matchers
+                .iter()
+                .any(|m| m.matches(host) || msg.all_hostnames().any(|h| m.matches(h)))
+    };
+
+    let response = if path == constants::KEYS_PATH {
+        if cached {
+            return Ok(not_modified(&etag));
+        }
+        let keys: BTreeMap<&String, &Message> = database
+            .keys
+            .iter()
+            .filter(|(host, msg)| selected(host, msg))
+            .collect();
+        json(serde_json::to_vec(&keys)?)
+    } else if let Some(host) = path
+        .strip_prefix(constants::KEYS_PATH)
+        .and_then(|v| v.strip_prefix('/'))
+    {
+        match database.keys.get(host) {
+            Some(_) if cached => return Ok(not_modified(&etag)),
+            Some(v) => json(serde_json::to_vec(v)?),
+            None => return Ok(text("not found", 404)),
+        }
+    } else if path == constants::KNOWN_HOSTS_PATH {
+        if cached {
+            return Ok(not_modified(&etag));
+        }
+        let mut keys = KeyMap::new();
+        for (host, msg) in database.keys.iter() {
+            if selected(host, msg) {
+                keys.insert(host, msg.clone());
+            }
+        }
+        text(&global::known_hosts::render(&keys), 200)
+    } else {
+        return Ok(text("not found", 404));
+    };
+
+    Ok(response.with_header(header("ETag", &etag)))
+}
+
+fn header(field: &str, value: &str) -> tiny_http::Header {
+    // XXX: Header names are static and values are ASCII, so the header is always valid
+    tiny_http::Header::from_bytes(field.as_bytes(), value.as_bytes()).unwrap()
+}
+
+fn json(body: Vec<u8>) -> Response {
+    tiny_http::Response::from_data(body).with_header(header("Content-Type", "application/json"))
+}
+
+fn text(body: &str, status: u16) -> Response {
+    tiny_http::Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header("Content-Type", "text/plain; charset=utf-8"))
+}
+
+fn not_modified(etag: &str) -> Response {
+    tiny_http::Response::from_data(Vec::new())
+        .with_status_code(304)
+        .with_header(header("ETag", etag))
+}
@@ -0,0 +1,134 @@
+use crate::config;
+use crate::database::{self, Shared};
+
+use global::encryption::Identities;
+use global::error::Error;
+use global::signals::Signals;
+use global::subscribe::{Handler, InitialSync};
+use global::transport::{Backend, Update};
+use log::{debug, error, info, warn};
+
+struct Subscriber<'a> {
+    cfg: config::Configuration,
+    config_file: String,
+    database: &'a Shared,
+    identities: Option<Identities>,
+    sync: InitialSync,
+    ready: bool,
+}
+
+pub fn run(
+    cfg: config::Configuration,
+    config_file: &str,
+    database: &Shared,
+    signals: &Signals,
+) -> Result<(), Error> {
+    let identities = load_identities(&cfg)?;
+    let mut subscriber = Subscriber {
+        cfg,
+        config_file: config_file.to_string(),
+        database,
+        identities,
+        sync: InitialSync::new(),
+        ready: false,
+    };
+    global::subscribe::run(&mut subscriber, signals)
+}
+
+impl Handler for Subscriber<'_> {
+    fn backend(&self) -> &Backend {
+        &self.cfg.backend
+    }
+
+    fn update(&mut self, update: Update, location: &str) -> Result<(), Error> {
+        match update {
+            Update::Data(host, payload) => {
+                self.sync.received();
+                database::write(self.database)?.update(&host, &payload, self.identities.as_ref());
+            }
+            Update::Connected => {
+                self.sync.connected();
+                global::systemd::notify_or_warn(&format!(
+                    "STATUS=receiving key data from {}",
+                    location
+                ));
+            }
+            Update::Disconnected => {}
+        };
+        Ok(())
+    }
+
+    fn reload(&mut self) -> Result<bool, Error> {
+        info!("reloading configuration from {}", self.config_file);
+        let mut new_cfg = match config::parse_config_file(&self.config_file) {
+            Ok(v) => v,
+            Err(e) => {
+                error!(
+                    "reloading configuration file {} failed, keeping current configuration: {}",
+                    self.config_file, e
+                );
+                return Ok(false);
+            }
+        };
+
+        if new_cfg.server != self.cfg.server {
+            warn!("changes of the server configuration require a restart, ignoring them");
+            new_cfg.server = self.cfg.server.clone();
+        }
+        let reconnect = self.cfg.backend.changed(&new_cfg.backend);
+        if !reconnect {
+            new_cfg.backend = self.cfg.backend.clone();
+        }
+        // Key data rejected with the old identities must be received again
+        let resubscribe = new_cfg.payload != self.cfg.payload;
+        match load_identities(&new_cfg) {
+            Ok(v) => self.identities = v,
+            Err(e) => error!("{}, keeping current identities", e),
+        };
+        debug!("reloaded configuration: {:?}", new_cfg);
+        self.cfg = new_cfg;
+        if reconnect {
+            // All key data will be received again from the new backend
+            info!("backend configuration changed, removing all data and reconnecting");
+            database::write(self.database)?.clear();
+            self.sync.restart();
+            return Ok(true);
+        }
+        if resubscribe {
+            info!("identity file changed, reconnecting");
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    // Key data is only served after the initial transfer, so clients never see partial data
+    fn tick(&mut self) -> Result<(), Error> {
+        if !self.sync.finished() {
+            return Ok(());
+        }
+
+        let mut database = database::write(self.database)?;
+        database.set_ready();
+        info!(
+            "initial transfer of key data finished, serving key data of {} hosts",
+            database.keys.hosts()
+        );
+        let mut state = format!(
+            "STATUS=serving key data from {}",
+            self.cfg.backend.location()
+        );
+        if !self.ready {
+            state.push_str("\nREADY=1");
+            self.ready = true;
+        }
+        global::systemd::notify_or_warn(&state);
+        Ok(())
+    }
+}
+
+fn load_identities(cfg: &config::Configuration) -> Result<Option<Identities>, Error> {
+    match &cfg.payload.identity_file {
+        Some(v) => Ok(Some(Identities::from_file(v)?)),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,33 @@
+use crate::constants;
+
+pub fn show_usage() {
+    global::usage::show_version();
+    println!(
+        "Usage: {} [-c <cfg>|--config=<cfg>] [-h|--help] [-L <fmt>|--log-format=<fmt>] [-q|--quiet] [-C|--check] [-D|--debug] [-V|--version]
+
+    -c <cfg>                    Read configuration from file <cfg>
+    --config=<cfg>              Default: {}
+
+    -h                          Shows this text
+    --help
+
+    -L <fmt>                    Log format, one of text, json, syslog or journald
+    --log-format=<fmt>          Overrides the log format from the configuration file
+
+    -q                          Quiet operation.
+    --quiet                     Only warning and critical messages will be logged
+
+    -C                          Check configuration file and exit
+    --check
+
+    -D                          Enable debug log
+    --debug
+
+    -V                          Show version information
+    --version
+
+",
+        env!("CARGO_BIN_NAME"),
+        constants::DEFAULT_CONFIG_FILE,
+    );
+}
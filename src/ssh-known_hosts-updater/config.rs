@@ -1,18 +1,65 @@
 use crate::constants;
 
+use global::error::Error;
 use serde::Deserialize;
-use simple_error::bail;
-use std::error::Error;
 use std::fs;
-use url::Url;
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Configuration {
-    pub mqtt: global::mqtt::MQTT,
+    #[serde(default)]
+    pub expiry: Expiry,
+    #[serde(default)]
+    pub logging: global::logging::Logging,
+    #[serde(default)]
+    pub metrics: Metrics,
+    #[serde(flatten)]
+    pub backend: global::transport::Backend,
+    #[serde(default)]
+    pub payload: Payload,
     #[serde(default)]
     pub ssh: Ssh,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+pub struct Expiry {
+    pub max_age: Option<u64>,
+    #[serde(default = "default_expiry_grace_period")]
+    pub grace_period: u64,
+    #[serde(default = "default_expiry_interval")]
+    pub interval: u64,
+}
+
+impl Default for Expiry {
+    fn default() -> Self {
+        Expiry {
+            max_age: None,
+            grace_period: default_expiry_grace_period(),
+            interval: default_expiry_interval(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Metrics {
+    pub listen: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Payload {
+    pub identity_file: Option<String>,
+    #[serde(default = "default_payload_max_size")]
+    pub max_size: usize,
+}
+
+impl Default for Payload {
+    fn default() -> Self {
+        Payload {
+            identity_file: None,
+            max_size: default_payload_max_size(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct Ssh {
     #[serde(default = "default_ssh_known_hosts_file")]
@@ -27,51 +74,77 @@ impl Default for Ssh {
     }
 }
 
+fn default_expiry_grace_period() -> u64 {
+    constants::DEFAULT_EXPIRY_GRACE_PERIOD
+}
+
+fn default_expiry_interval() -> u64 {
+    constants::DEFAULT_EXPIRY_INTERVAL
+}
+
+fn default_payload_max_size() -> usize {
+    global::constants::DEFAULT_MAX_PAYLOAD_SIZE
+}
+
 fn default_ssh_known_hosts_file() -> String {
     constants::DEFAULT_SSH_KNOWN_HOSTS_FILE.to_string()
 }
 
-pub fn parse_config_file(f: &str) -> Result<Configuration, Box<dyn Error>> {
-    let raw = fs::read_to_string(f)?;
+pub fn parse_config_file(f: &str) -> Result<Configuration, Error> {
+    let raw = match fs::read_to_string(f) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(Error::Config(format!("can't read {}: {}", f, e)));
+        }
+    };
     let mut parsed: Configuration = serde_yaml::from_str(raw.as_str())?;
 
     validate(&parsed)?;
 
-    parsed.mqtt.topic = parsed.mqtt.topic.trim_end_matches('/').to_string();
+    if let Some(mqtt) = parsed.backend.mqtt.as_mut() {
+        mqtt.topic = mqtt.topic.trim_end_matches('/').to_string();
+    }
 
     Ok(parsed)
 }
 
-fn validate(cfg: &Configuration) -> Result<(), Box<dyn Error>> {
-    if cfg.mqtt.qos > 2 || cfg.mqtt.qos < 0 {
-        bail!("invalid MQTT QoS setting");
+fn validate(cfg: &Configuration) -> Result<(), Error> {
+    cfg.backend.validate()?;
+
+    if let Some(mqtt) = &cfg.backend.mqtt {
+        global::mqtt::validate(mqtt, global::mqtt::Topic::Subscribe)?;
     }
 
-    if cfg.mqtt.topic.is_empty() || (!cfg.mqtt.topic.contains('+') && !cfg.mqtt.topic.contains('#'))
-    {
-        bail!("invalid MQTT topic, wildcards must be present in subscribed topic")
+    if let Some(v) = &cfg.metrics.listen {
+        if v.parse::<std::net::SocketAddr>().is_err() {
+            return Err(Error::Config(format!(
+                "invalid listen address {} for metrics",
+                v
+            )));
+        }
     }
 
-    if cfg.mqtt.timeout == 0 {
-        bail!("invalid MQTT timeout");
+    if cfg.expiry.max_age == Some(0) {
+        return Err(Error::Config("invalid maximal age for expiry".to_string()));
     }
 
-    if cfg.mqtt.reconnect_timeout == 0 {
-        bail!("invalid MQTT reconnect timeout");
+    if cfg.expiry.interval == 0 {
+        return Err(Error::Config("invalid expiry interval".to_string()));
     }
 
-    if let Err(e) = validate_url(&cfg.mqtt.broker) {
-        bail!("invalid MQTT broker url: {}", e);
+    if cfg.payload.max_size == 0 {
+        return Err(Error::Config("invalid maximal payload size".to_string()));
     }
 
-    if cfg.ssh.known_hosts_file.is_empty() {
-        bail!("empty value for ssh known_hosts file");
+    if let Some(v) = &cfg.payload.identity_file {
+        global::encryption::Identities::from_file(v)?;
     }
 
-    Ok(())
-}
+    if cfg.ssh.known_hosts_file.is_empty() {
+        return Err(Error::Config(
+            "empty value for ssh known_hosts file".to_string(),
+        ));
+    }
 
-fn validate_url(s: &str) -> Result<(), Box<dyn Error>> {
-    let _parsed = Url::parse(s)?;
     Ok(())
 }
@@ -1,3 +1,7 @@
 pub const DEFAULT_SSH_KNOWN_HOSTS_FILE: &str = "/etc/ssh/ssh_known_hosts";
 pub const DEFAULT_CONFIG_FILE: &str = "/etc/ssh-known_hosts-updater.yaml";
 pub const DEFAULT_TMPDIR: &str = "/tmp";
+pub const METRICS_PREFIX: &str = "ssh_known_hosts_updater";
+pub const METRICS_PATH: &str = "/metrics";
+pub const DEFAULT_EXPIRY_GRACE_PERIOD: u64 = 3600;
+pub const DEFAULT_EXPIRY_INTERVAL: u64 = 300;
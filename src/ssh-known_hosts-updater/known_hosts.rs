@@ -1,122 +1,247 @@
 use crate::config;
 use crate::constants;
+use crate::metrics::{Metrics, METRICS};
 
+use global::encryption::Identities;
+use global::error::Error;
+use global::state::{Change, KeyMap};
+use global::subscribe::InitialSync;
 use log::{debug, error, info};
-use mktemp::Temp;
-use std::collections::HashMap;
 use std::env;
-use std::error::Error;
-use std::fs;
 use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+pub enum Event {
+    Connected,
+    Message(String, Vec<u8>),
+    Reload(Box<config::Configuration>),
+    Rewrite,
+    Shutdown,
+}
 
 pub fn update(
-    cfg: &config::Configuration,
-    receiver: mpsc::Receiver<paho_mqtt::message::Message>,
-) -> Result<(), Box<dyn Error>> {
-    let mut ssh_known_hosts: HashMap<String, global::payload::Message> = HashMap::new();
+    mut cfg: config::Configuration,
+    receiver: mpsc::Receiver<Event>,
+) -> Result<(), Error> {
+    let mut ssh_known_hosts = KeyMap::new();
+    ssh_known_hosts.set_max_payload_size(cfg.payload.max_size);
+    let mut identities = load_identities(&cfg)?;
+    let mut sync = InitialSync::new();
+    let mut connected_since: Option<Instant> = None;
+    let mut last_sweep = Instant::now();
     let tempdir = match env::var("TMPDIR") {
         Ok(v) => v,
         Err(_) => constants::DEFAULT_TMPDIR.to_string(),
     };
 
     loop {
-        let mqtt_msg = receiver.recv()?;
-
-        debug!("MQTT message received for processing");
-
-        let mut update = false;
-        let topic = mqtt_msg.topic();
-        let payload = mqtt_msg.payload().to_vec();
-        let mut spl_tpc: Vec<&str> = topic.split('/').collect();
-
-        let key = match spl_tpc.pop() {
-            Some(v) => v.to_string(),
-            None => {
-                panic!("BUG: empty topic - shouldn't happen");
+        let mut timeout =
+            Duration::from_secs(cfg.expiry.interval).saturating_sub(last_sweep.elapsed());
+        if let Some(v) = sync.remaining() {
+            timeout = timeout.min(v);
+        }
+        let event = match receiver.recv_timeout(timeout) {
+            Ok(v) => Some(v),
+            Err(mpsc::RecvTimeoutError::Timeout) => None,
+            Err(e) => {
+                return Err(Error::Io(format!(
+                    "can't receive key data from subscription handler: {}",
+                    e
+                )));
             }
         };
 
-        debug!("parsing MQTT message");
-        if payload.is_empty() {
-            info!("empty message received for {}, removing data from map", key);
-            ssh_known_hosts.remove(&key);
-            update = true
-        } else {
-            let msg = match parse_data(payload) {
-                Ok(v) => v,
-                Err(e) => {
-                    error!("can't parse message payload: {}", e);
-                    continue;
+        let mut update = match event {
+            None => false,
+            Some(Event::Connected) => {
+                debug!("connection to backend established");
+                connected_since = Some(Instant::now());
+                sync.connected();
+                false
+            }
+            Some(Event::Message(host, payload)) => {
+                sync.received();
+                process_message(
+                    &cfg,
+                    &mut ssh_known_hosts,
+                    identities.as_ref(),
+                    &host,
+                    &payload,
+                )
+            }
+            Some(Event::Reload(new_cfg)) => {
+                // All key data will be received again from the new backend
+                if cfg.backend.changed(&new_cfg.backend) {
+                    info!("backend configuration changed, removing all data from map");
+                    ssh_known_hosts.clear();
                 }
-            };
+                ssh_known_hosts.set_max_payload_size(new_cfg.payload.max_size);
+                match load_identities(&new_cfg) {
+                    Ok(v) => identities = v,
+                    Err(e) => error!("{}, keeping current identities", e),
+                };
+                let update = new_cfg.ssh.known_hosts_file != cfg.ssh.known_hosts_file;
+                cfg = *new_cfg;
+                update
+            }
+            Some(Event::Rewrite) => {
+                info!("rewrite of {} requested", cfg.ssh.known_hosts_file);
+                true
+            }
+            Some(Event::Shutdown) => {
+                info!("all pending key data processed, stopping data handler");
+                return Ok(());
+            }
+        };
 
-            debug!("processing MQTT message for {}", key);
-            if msg.keys.is_empty() {
-                debug!("key list is empty, removing {} from map", key);
-                ssh_known_hosts.remove(&key);
-                update = true;
-            } else if let Some(oldvalue) = ssh_known_hosts.get(&key) {
-                debug!("processing non-empty data for {}", key);
-                if *oldvalue != msg {
-                    debug!("key information for {} changed, updating data", key);
-                    ssh_known_hosts.insert(key, msg);
-                    update = true;
-                } else {
-                    info!(
-                        "key information for {} has not changed, skipping update of {}",
-                        key, cfg.ssh.known_hosts_file,
-                    );
-                }
-            } else {
-                debug!("SSH key data not found for {}, inserting data", key);
-                ssh_known_hosts.insert(key, msg);
+        if last_sweep.elapsed() >= Duration::from_secs(cfg.expiry.interval) {
+            last_sweep = Instant::now();
+            if expire(&cfg, &mut ssh_known_hosts, connected_since) {
                 update = true;
             }
         }
+
         if update {
-            if let Err(e) =
-                update_ssh_known_hosts_file(&cfg.ssh.known_hosts_file, &ssh_known_hosts, &tempdir)
-            {
-                error!("can't update {}: {}", cfg.ssh.known_hosts_file, e);
-            }
+            write_ssh_known_hosts(&cfg, &ssh_known_hosts, &tempdir);
+        }
+
+        if sync.finished() {
+            info!("initial transfer of key data finished");
+            global::systemd::notify_or_warn(&format!(
+                "READY=1\nSTATUS={} hosts in {} from {}",
+                ssh_known_hosts.hosts(),
+                cfg.ssh.known_hosts_file,
+                cfg.backend.location()
+            ));
         }
     }
 }
 
-fn parse_data(raw: Vec<u8>) -> Result<global::payload::Message, Box<dyn Error>> {
-    let raw_str = String::from_utf8(raw)?;
-    let parsed = serde_json::from_str(&raw_str)?;
-    Ok(parsed)
+// The data handler thread loads the identities, they can't be passed between threads
+fn load_identities(cfg: &config::Configuration) -> Result<Option<Identities>, Error> {
+    match &cfg.payload.identity_file {
+        Some(v) => Ok(Some(Identities::from_file(v)?)),
+        None => Ok(None),
+    }
 }
 
-fn update_ssh_known_hosts_file(
-    file: &str,
-    data: &HashMap<String, global::payload::Message>,
-    tmpdir: &str,
-) -> Result<(), Box<dyn Error>> {
-    let mut keys: Vec<String> = Vec::new();
-    let tempfile = Temp::new_file_in(tmpdir)?;
-    if let Some(tempfile_name) = tempfile.to_str() {
-        for value in data.values() {
-            for key in value.keys.iter() {
-                keys.push(format!(
-                    "{} {} {} {}",
-                    value.hostname.join(","),
-                    key.key_type,
-                    key.key,
-                    key.comment
-                ));
-            }
+fn process_message(
+    cfg: &config::Configuration,
+    ssh_known_hosts: &mut KeyMap,
+    identities: Option<&Identities>,
+    host: &str,
+    payload: &[u8],
+) -> bool {
+    debug!("processing key data for {}", host);
+    Metrics::inc(&METRICS.messages_received);
+
+    let result = match global::encryption::decrypt(payload, identities, cfg.payload.max_size) {
+        Ok(v) => ssh_known_hosts.update(host, &v),
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(Change::Added) => {
+            debug!(host = host; "SSH key data not found for {}, inserting data", host);
+            log_fingerprints(host, ssh_known_hosts);
+            true
+        }
+        Ok(Change::Changed) => {
+            debug!(host = host; "key information for {} changed, updating data", host);
+            log_fingerprints(host, ssh_known_hosts);
+            true
+        }
+        Ok(Change::Removed) => {
+            info!(
+                host = host;
+                "empty message or key list received for {}, removing data from map", host
+            );
+            true
+        }
+        Ok(Change::Unchanged) => {
+            info!(
+                host = host;
+                "key information for {} has not changed, skipping update of {}",
+                host, cfg.ssh.known_hosts_file,
+            );
+            false
+        }
+        Err(Error::Validation(e)) => {
+            error!(host = host; "rejecting key data for {}: {}", host, e);
+            Metrics::inc(&METRICS.messages_rejected);
+            false
+        }
+        Err(e) => {
+            error!(host = host; "can't parse key data of {}: {}", host, e);
+            Metrics::inc(&METRICS.parse_errors);
+            false
+        }
+    }
+}
+
+// Collectors need some time to publish their key data again after an outage of the backend,
+// so nothing expires until the connection to the backend was established for the grace period
+fn expire(
+    cfg: &config::Configuration,
+    ssh_known_hosts: &mut KeyMap,
+    connected_since: Option<Instant>,
+) -> bool {
+    let max_age = match cfg.expiry.max_age {
+        Some(v) => v,
+        None => return false,
+    };
+
+    match connected_since {
+        Some(v) if v.elapsed() >= Duration::from_secs(cfg.expiry.grace_period) => {}
+        _ => {
+            debug!("grace period for expiry has not passed yet, skipping expiry");
+            return false;
         }
+    };
 
-        info!("writing key data to {}", tempfile_name);
-        let mut content = keys.join("\n");
-        content.push('\n');
+    let now = chrono::Utc::now().timestamp();
+    let expired = ssh_known_hosts.expire(max_age, now);
+    for (host, msg) in expired.iter() {
+        info!(
+            host = host;
+            "key data of {} was published {} seconds ago, removing expired data from map",
+            host,
+            now - msg.published.unwrap_or_default()
+        );
+        Metrics::inc(&METRICS.hosts_expired);
+    }
+
+    !expired.is_empty()
+}
 
-        fs::write(tempfile_name, content)?;
+fn write_ssh_known_hosts(cfg: &config::Configuration, ssh_known_hosts: &KeyMap, tempdir: &str) {
+    Metrics::set(&METRICS.hosts, ssh_known_hosts.hosts() as u64);
+    Metrics::set(&METRICS.keys, ssh_known_hosts.keys() as u64);
+    if let Err(e) = global::known_hosts::write(&cfg.ssh.known_hosts_file, ssh_known_hosts, tempdir)
+    {
+        error!("can't update {}: {}", cfg.ssh.known_hosts_file, e);
+        Metrics::inc(&METRICS.write_failures);
+    } else {
+        Metrics::inc(&METRICS.writes);
+        Metrics::set(&METRICS.last_write, chrono::Utc::now().timestamp() as u64);
 
-        info!("replacing {} with new content from {}", file, tempfile_name);
-        fs::rename(tempfile_name, file)?;
+        global::systemd::notify_or_warn(&format!(
+            "STATUS={} hosts in {} from {}",
+            ssh_known_hosts.hosts(),
+            cfg.ssh.known_hosts_file,
+            cfg.backend.location()
+        ));
+    }
+}
+
+fn log_fingerprints(host: &str, ssh_known_hosts: &KeyMap) {
+    if let Some(msg) = ssh_known_hosts.get(host) {
+        for key in msg.keys.iter() {
+            if let Some(fingerprint) = key.fingerprint() {
+                debug!(
+                    host = host, fingerprint = fingerprint;
+                    "{} key of {} has fingerprint {}", key.key_type, host, fingerprint
+                );
+            }
+        }
     }
-    Ok(())
 }
@@ -1,7 +1,8 @@
 mod config;
 mod constants;
 mod known_hosts;
-mod mqtt;
+mod metrics;
+mod subscribe;
 mod usage;
 
 use getopts::Options;
@@ -25,6 +26,12 @@ fn main() {
         "Configuration file",
         constants::DEFAULT_CONFIG_FILE,
     );
+    options.optopt(
+        "L",
+        "log-format",
+        "Log format: text, json, syslog or journald",
+        "text",
+    );
     options.optflag("q", "quiet", "Quiet operation");
 
     let opts = match options.parse(&argv[1..]) {
@@ -33,7 +40,7 @@ fn main() {
             eprintln!("Error: Can't parse command line arguments: {}", e);
             println!();
             usage::show_usage();
-            process::exit(1);
+            process::exit(global::constants::EXIT_USAGE_ERROR);
         }
     };
 
@@ -60,20 +67,43 @@ fn main() {
         None => constants::DEFAULT_CONFIG_FILE.to_string(),
     };
 
-    // XXX: Initialisation of logging should never fail
-    global::logging::init(log_level).unwrap();
+    let log_format = match opts.opt_str("L") {
+        Some(v) => match v.parse::<global::logging::Format>() {
+            Ok(v) => Some(v),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                println!();
+                usage::show_usage();
+                process::exit(global::constants::EXIT_USAGE_ERROR);
+            }
+        },
+        None => None,
+    };
 
     let configuration = match config::parse_config_file(&config_file) {
         Ok(v) => v,
         Err(e) => {
+            global::logging::init_or_exit(
+                log_level,
+                &global::logging::Logging::default(),
+                log_format,
+                global::logging::Output::Stdout,
+            );
             error!(
                 "parsing of configuration file {} failed: {}",
                 config_file, e
             );
-            process::exit(1);
+            process::exit(e.exit_code());
         }
     };
 
+    global::logging::init_or_exit(
+        log_level,
+        &configuration.logging,
+        log_format,
+        global::logging::Output::Stdout,
+    );
+
     if opts.opt_present("C") {
         info!("configuration file {} is valid", config_file);
         process::exit(0);
@@ -81,26 +111,44 @@ fn main() {
 
     debug!("parsed configuration: {:?}", configuration);
 
-    let (send, receive) = mpsc::channel::<paho_mqtt::message::Message>();
+    let signals = match global::signals::Signals::register() {
+        Ok(v) => v,
+        Err(e) => {
+            error!("can't register signal handlers: {}", e);
+            process::exit(e.exit_code());
+        }
+    };
+
+    let (send, receive) = mpsc::channel::<known_hosts::Event>();
     let cfg = configuration.clone();
 
-    let thread_id = thread::spawn(move || {
-        match known_hosts::update(&cfg, receive) {
-            Ok(_) => {
-                process::exit(0);
-            }
-            Err(e) => {
-                eprintln!("data handler thread failed: {}", e);
-                process::exit(1);
+    let thread_id = thread::spawn(move || known_hosts::update(cfg, receive));
+
+    if let Some(listen) = configuration.metrics.listen.clone() {
+        thread::spawn(move || {
+            if let Err(e) = metrics::serve(&listen) {
+                error!("metrics listener failed: {}", e);
             }
-        };
-    });
+        });
+    }
 
-    if let Err(e) = mqtt::run(&configuration, send) {
-        error!("MQTT thread failed to start: {}", e);
-        process::exit(1);
+    if let Err(e) = subscribe::run(configuration, &config_file, send, &signals) {
+        error!("subscription to key data failed: {}", e);
+        process::exit(e.exit_code());
     }
 
-    thread_id.join().unwrap();
-    process::exit(0);
+    match thread_id.join() {
+        Ok(Ok(_)) => {
+            info!("shutdown complete");
+            process::exit(0);
+        }
+        Ok(Err(e)) => {
+            error!("data handler thread failed: {}", e);
+            process::exit(e.exit_code());
+        }
+        Err(_) => {
+            error!("data handler thread panicked");
+            process::exit(global::constants::EXIT_IO_ERROR);
+        }
+    }
 }
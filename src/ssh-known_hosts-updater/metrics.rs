@@ -0,0 +1,168 @@
+use crate::constants;
+
+use global::error::Error;
+use log::{debug, error, info};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub struct Metrics {
+    pub hosts: AtomicU64,
+    pub keys: AtomicU64,
+    pub hosts_expired: AtomicU64,
+    pub messages_received: AtomicU64,
+    pub messages_rejected: AtomicU64,
+    pub parse_errors: AtomicU64,
+    pub writes: AtomicU64,
+    pub write_failures: AtomicU64,
+    pub last_write: AtomicU64,
+    pub backend_connected: AtomicU64,
+    pub backend_reconnects: AtomicU64,
+}
+
+pub static METRICS: Metrics = Metrics {
+    hosts: AtomicU64::new(0),
+    keys: AtomicU64::new(0),
+    hosts_expired: AtomicU64::new(0),
+    messages_received: AtomicU64::new(0),
+    messages_rejected: AtomicU64::new(0),
+    parse_errors: AtomicU64::new(0),
+    writes: AtomicU64::new(0),
+    write_failures: AtomicU64::new(0),
+    last_write: AtomicU64::new(0),
+    backend_connected: AtomicU64::new(0),
+    backend_reconnects: AtomicU64::new(0),
+};
+
+impl Metrics {
+    pub fn inc(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set(gauge: &AtomicU64, value: u64) {
+        gauge.store(value, Ordering::Relaxed);
+    }
+
+    fn render(&self) -> String {
+        let mut result = String::new();
+        let metrics: [(&str, &str, &str, &AtomicU64); 11] = [
+            (
+                "hosts",
+                "gauge",
+                "Number of hosts in the SSH key map",
+                &self.hosts,
+            ),
+            (
+                "keys",
+                "gauge",
+                "Number of SSH keys in the SSH key map",
+                &self.keys,
+            ),
+            (
+                "hosts_expired_total",
+                "counter",
+                "Number of hosts removed from the SSH key map because of expired key data",
+                &self.hosts_expired,
+            ),
+            (
+                "messages_received_total",
+                "counter",
+                "Number of key data messages received from the backend",
+                &self.messages_received,
+            ),
+            (
+                "messages_rejected_total",
+                "counter",
+                "Number of key data messages rejected because of invalid key data",
+                &self.messages_rejected,
+            ),
+            (
+                "parse_errors_total",
+                "counter",
+                "Number of key data messages with unparseable payload",
+                &self.parse_errors,
+            ),
+            (
+                "known_hosts_writes_total",
+                "counter",
+                "Number of successful rewrites of the ssh_known_hosts file",
+                &self.writes,
+            ),
+            (
+                "known_hosts_write_failures_total",
+                "counter",
+                "Number of failed rewrites of the ssh_known_hosts file",
+                &self.write_failures,
+            ),
+            (
+                "known_hosts_last_write_timestamp_seconds",
+                "gauge",
+                "UNIX timestamp of the last successful rewrite of the ssh_known_hosts file",
+                &self.last_write,
+            ),
+            (
+                "backend_connected",
+                "gauge",
+                "Connection state to the backend, 1 if connected",
+                &self.backend_connected,
+            ),
+            (
+                "backend_reconnects_total",
+                "counter",
+                "Number of reconnects to the backend",
+                &self.backend_reconnects,
+            ),
+        ];
+
+        for (name, kind, help, value) in metrics {
+            // XXX: Writing to a String can't fail
+            let _ = write!(
+                result,
+                "# HELP {prefix}_{name} {help}\n# TYPE {prefix}_{name} {kind}\n{prefix}_{name} {value}\n",
+                prefix = constants::METRICS_PREFIX,
+                name = name,
+                help = help,
+                kind = kind,
+                value = value.load(Ordering::Relaxed),
+            );
+        }
+        result
+    }
+}
+
+pub fn serve(listen: &str) -> Result<(), Error> {
+    let server = match tiny_http::Server::http(listen) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(Error::Io(format!(
+                "can't start metrics listener on {}: {}",
+                listen, e
+            )));
+        }
+    };
+    info!(
+        "serving metrics on http://{}{}",
+        listen,
+        constants::METRICS_PATH
+    );
+
+    for request in server.incoming_requests() {
+        debug!("metrics request {} {}", request.method(), request.url());
+        let response = if *request.method() != tiny_http::Method::Get {
+            tiny_http::Response::from_string("method not allowed").with_status_code(405)
+        } else if request.url() != constants::METRICS_PATH {
+            tiny_http::Response::from_string("not found").with_status_code(404)
+        } else {
+            // XXX: Header is static and always valid
+            let header = tiny_http::Header::from_bytes(
+                &b"Content-Type"[..],
+                &b"text/plain; version=0.0.4"[..],
+            )
+            .unwrap();
+            tiny_http::Response::from_string(METRICS.render()).with_header(header)
+        };
+        if let Err(e) = request.respond(response) {
+            error!("can't send metrics response: {}", e);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use crate::config;
+use crate::known_hosts::Event;
+use crate::metrics::{Metrics, METRICS};
+
+use global::error::Error;
+use global::signals::Signals;
+use global::subscribe::Handler;
+use global::transport::{Backend, Update};
+use log::{debug, error, info};
+use std::sync::mpsc;
+
+struct Subscriber {
+    cfg: config::Configuration,
+    config_file: String,
+    sender: mpsc::Sender<Event>,
+    connected_before: bool,
+}
+
+pub fn run(
+    cfg: config::Configuration,
+    config_file: &str,
+    sender: mpsc::Sender<Event>,
+    signals: &Signals,
+) -> Result<(), Error> {
+    let mut subscriber = Subscriber {
+        cfg,
+        config_file: config_file.to_string(),
+        sender,
+        connected_before: false,
+    };
+    global::subscribe::run(&mut subscriber, signals)
+}
+
+impl Handler for Subscriber {
+    fn backend(&self) -> &Backend {
+        &self.cfg.backend
+    }
+
+    fn update(&mut self, update: Update, location: &str) -> Result<(), Error> {
+        match update {
+            Update::Data(host, payload) => {
+                debug!("sending key data of {} to data handler", host);
+                self.send(Event::Message(host, payload))?;
+            }
+            Update::Connected => {
+                Metrics::set(&METRICS.backend_connected, 1);
+                if self.connected_before {
+                    Metrics::inc(&METRICS.backend_reconnects);
+                }
+                self.connected_before = true;
+                self.send(Event::Connected)?;
+                global::systemd::notify_or_warn(&format!("STATUS=connected to {}", location));
+            }
+            Update::Disconnected => {
+                Metrics::set(&METRICS.backend_connected, 0);
+            }
+        };
+        Ok(())
+    }
+
+    fn reload(&mut self) -> Result<bool, Error> {
+        info!("reloading configuration from {}", self.config_file);
+        let mut new_cfg = match config::parse_config_file(&self.config_file) {
+            Ok(v) => v,
+            Err(e) => {
+                error!(
+                    "reloading configuration file {} failed, keeping current configuration: {}",
+                    self.config_file, e
+                );
+                return Ok(false);
+            }
+        };
+
+        let reconnect = self.cfg.backend.changed(&new_cfg.backend);
+        if !reconnect {
+            new_cfg.backend = self.cfg.backend.clone();
+        }
+        // Key data rejected with the old identities must be received again
+        let resubscribe = new_cfg.payload.identity_file != self.cfg.payload.identity_file;
+        debug!("reloaded configuration: {:?}", new_cfg);
+        self.send(Event::Reload(Box::new(new_cfg.clone())))?;
+        self.cfg = new_cfg;
+        if reconnect {
+            info!("backend configuration changed, reconnecting");
+            return Ok(true);
+        }
+        if resubscribe {
+            info!("identity file changed, reconnecting");
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    fn rewrite(&mut self) -> Result<(), Error> {
+        self.send(Event::Rewrite)
+    }
+
+    fn shutdown(&mut self) -> Result<(), Error> {
+        self.send(Event::Shutdown)
+    }
+}
+
+impl Subscriber {
+    fn send(&self, event: Event) -> Result<(), Error> {
+        if let Err(e) = self.sender.send(event) {
+            return Err(Error::Io(format!(
+                "can't send event to data handler: {}",
+                e
+            )));
+        }
+        Ok(())
+    }
+}